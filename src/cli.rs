@@ -0,0 +1,145 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::site::{config::SiteConfig, options::SiteBuildOptions};
+
+// exit codes follow sysexits(3) so scripts can tell bad content from a
+// machine problem; clap itself exits with 2 on usage errors
+pub struct CliStatus;
+impl CliStatus {
+  pub const CONTENT_ERROR: u8 = 65;
+  pub const CANNOT_CREATE: u8 = 73;
+  pub const IO_ERROR: u8 = 74;
+  // shutdown cut off connections that did not finish within the drain timeout
+  pub const TEMPORARY_FAILURE: u8 = 75;
+  pub const CONFIG_ERROR: u8 = 78;
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Builds and serves the site from content/")]
+pub struct Cli {
+  /// Site configuration file
+  #[arg(long, global = true, default_value = SiteConfig::FILE)]
+  pub config: PathBuf,
+  #[command(subcommand)]
+  pub command: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+  /// Compile the site into the output directory
+  Build(BuildArgs),
+  /// Serve the compiled site, building it first unless told not to
+  Serve(ServeArgs),
+  /// Build the site in a temporary directory and report broken routes, links and blocked inline content
+  Check(CheckArgs),
+  /// Scaffold new content
+  New {
+    #[command(subcommand)]
+    kind: NewKind,
+  },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NewKind {
+  /// Create a draft blog post dated today
+  Post {
+    title: String,
+  },
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BuildArgs {
+  /// Directory to write the compiled site to [default: from site.toml]
+  #[arg(long)]
+  pub out: Option<PathBuf>,
+  /// Include drafts and posts scheduled for the future
+  #[arg(long)]
+  pub drafts: bool,
+  /// Absolute URL the site is published under, used in feeds and the sitemap
+  #[arg(long)]
+  pub base_url: Option<String>,
+  /// Recompile every page instead of only those whose inputs changed; static
+  /// files, images, CSS, feeds and fingerprints are always processed in full
+  #[arg(long)]
+  pub clean: bool,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct ServeArgs {
+  /// Address to listen on [default: from site.toml]
+  #[arg(long)]
+  pub addr: Option<IpAddr>,
+  /// Port to listen on [default: from site.toml]
+  #[arg(long)]
+  pub port: Option<u16>,
+  /// Directory to build into and serve from [default: from site.toml]
+  #[arg(long)]
+  pub root: Option<PathBuf>,
+  /// Serve the root as it is without building first
+  #[arg(long, conflicts_with = "watch")]
+  pub no_build: bool,
+  /// Rebuild when the content directory changes and reload open browsers
+  #[arg(long)]
+  pub watch: bool,
+  /// Include drafts and posts scheduled for the future
+  #[arg(long)]
+  pub drafts: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CheckArgs {
+  /// Include drafts and posts scheduled for the future
+  #[arg(long)]
+  pub drafts: bool,
+}
+
+// flags given on the command line take precedence over site.toml
+impl BuildArgs {
+  pub fn options(&self, mut site: SiteConfig) -> SiteBuildOptions {
+    if let Some(out) = &self.out {
+      site.directories.output = out.clone();
+    }
+    if let Some(base_url) = &self.base_url {
+      site.base_url = base_url.clone();
+    }
+    SiteBuildOptions {
+      site,
+      include_drafts: self.drafts,
+      incremental: !self.clean,
+      ..Default::default()
+    }
+  }
+}
+
+impl ServeArgs {
+  pub fn options(&self, mut site: SiteConfig) -> SiteBuildOptions {
+    if let Some(root) = &self.root {
+      site.directories.output = root.clone();
+    }
+    if let Some(addr) = self.addr {
+      site.server.addr = addr;
+    }
+    if let Some(port) = self.port {
+      site.server.port = port;
+    }
+    SiteBuildOptions {
+      site,
+      include_drafts: self.drafts,
+      ..Default::default()
+    }
+  }
+}
+
+impl CheckArgs {
+  pub fn options(&self, site: SiteConfig) -> SiteBuildOptions {
+    SiteBuildOptions {
+      site,
+      include_drafts: self.drafts,
+      check: true,
+      ..Default::default()
+    }
+  }
+}
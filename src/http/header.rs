@@ -12,7 +12,16 @@ impl HttpHeader {
     }
   }
 
-  pub fn get_header(headers: &Vec<HttpHeader>, header_name: &str) -> Option<String> {
+  // the name part of a Host header, keeping bracketed IPv6 addresses intact
+  pub fn host_name(host: &str) -> &str {
+    let host = host.trim();
+    match host.rsplit_once(':') {
+      Some((name, port)) if !port.contains(']') => name,
+      _ => host,
+    }
+  }
+
+  pub fn get_header(headers: &[HttpHeader], header_name: &str) -> Option<String> {
     headers
       .iter()
       .find(|h| h.name.to_lowercase() == header_name.to_lowercase())
@@ -9,12 +9,19 @@ pub struct Metadata {
 }
 
 impl Metadata {
+  // stands in for a request that was answered before it could be read, and
+  // is logged without a request line
+  pub fn unread(protocol: HttpProtocol) -> Metadata {
+    Metadata { method: HttpMethod::Get, path: String::new(), protocol, headers: vec![] }
+  }
+
   pub fn validate(&self) -> Result<(), HttpError> {
     match self.protocol {
       HttpProtocol::Http10 => {
         Ok(())
       }
-      HttpProtocol::Http11 => {
+      // http/2 requests carry the host as :authority, which becomes Host
+      HttpProtocol::Http11 | HttpProtocol::Http2 => {
         HttpHeader::get_header(&self.headers, "Host")
           .map(|_| ())
           .ok_or(HttpError::MissingHostHeader)
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::str::FromStr;
 
 use crate::http::error::HttpError;
@@ -15,19 +16,19 @@ pub enum HttpMethod {
   Patch
 }
 
-impl ToString for HttpMethod {
-    fn to_string(&self) -> String {
-        match self {
-          HttpMethod::Get => String::from("GET"),
-          HttpMethod::Post => String::from("POST"),
-          HttpMethod::Put => String::from("PUT"),
-          HttpMethod::Head => String::from("HEAD"),
-          HttpMethod::Delete => String::from("DELETE"),
-          HttpMethod::Connect => String::from("CONNECT"),
-          HttpMethod::Options => String::from("OPTIONS"),
-          HttpMethod::Trace => String::from("TRACE"),
-          HttpMethod::Patch => String::from("PATCH"),
-        }
+impl fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+          HttpMethod::Get => "GET",
+          HttpMethod::Post => "POST",
+          HttpMethod::Put => "PUT",
+          HttpMethod::Head => "HEAD",
+          HttpMethod::Delete => "DELETE",
+          HttpMethod::Connect => "CONNECT",
+          HttpMethod::Options => "OPTIONS",
+          HttpMethod::Trace => "TRACE",
+          HttpMethod::Patch => "PATCH",
+        })
     }
 }
 
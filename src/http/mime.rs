@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub struct HttpMime;
+impl HttpMime {
+  pub const DEFAULT: &str = "application/octet-stream";
+
+  // text types carry a charset so browsers never have to guess the encoding
+  const TYPES: &[(&str, &str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json; charset=utf-8"),
+    ("map", "application/json; charset=utf-8"),
+    ("webmanifest", "application/manifest+json; charset=utf-8"),
+    ("xml", "application/xml; charset=utf-8"),
+    ("rss", "application/rss+xml; charset=utf-8"),
+    ("atom", "application/atom+xml; charset=utf-8"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("svg", "image/svg+xml; charset=utf-8"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("wasm", "application/wasm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+  ];
+
+  // overrides are keyed by extension and take precedence over the table
+  pub fn from_extension(path: impl AsRef<Path>, overrides: &BTreeMap<String, String>) -> Option<String> {
+    let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+    overrides.get(&extension)
+      .cloned()
+      .or_else(|| Self::TYPES.iter().find(|(e, _)| *e == extension).map(|(_, mime)| String::from(*mime)))
+  }
+
+  // ISO base media brands that mean plain mp4 video; others, like HEIC, share
+  // the container but are not mp4
+  const MP4_BRANDS: [&[u8]; 13] = [
+    b"isom", b"iso2", b"iso3", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"mp71", b"avc1", b"dash", b"M4V ", b"msnv",
+  ];
+
+  // "BM", then the file size and two reserved fields that are always zero
+  fn is_bmp(body: &[u8]) -> bool {
+    match (body.get(..2), body.get(2..6), body.get(6..10)) {
+      (Some(b"BM"), Some(size), Some(reserved)) => {
+        u32::from_le_bytes(size.try_into().expect("Slice is four bytes")) as usize == body.len() && reserved == [0; 4]
+      }
+      _ => false,
+    }
+  }
+
+  // recognises a file by its leading bytes, for files with no or an unknown
+  // extension
+  pub fn sniff(body: &[u8]) -> Option<&'static str> {
+    let signatures: &[(&[u8], &str)] = &[
+      (b"\x89PNG\r\n\x1a\n", "image/png"),
+      (b"\xff\xd8\xff", "image/jpeg"),
+      (b"GIF87a", "image/gif"),
+      (b"GIF89a", "image/gif"),
+      (b"\x00\x00\x01\x00", "image/x-icon"),
+      (b"%PDF-", "application/pdf"),
+      (b"PK\x03\x04", "application/zip"),
+      (b"\x1f\x8b", "application/gzip"),
+      (b"\x00asm", "application/wasm"),
+      (b"wOFF", "font/woff"),
+      (b"wOF2", "font/woff2"),
+      (b"OTTO", "font/otf"),
+      (b"\x00\x01\x00\x00", "font/ttf"),
+      (b"ID3", "audio/mpeg"),
+      (b"OggS", "audio/ogg"),
+      (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    if let Some((_, mime)) = signatures.iter().find(|(signature, _)| body.starts_with(signature)) {
+      return Some(mime);
+    }
+    if Self::is_bmp(body) {
+      return Some("image/bmp");
+    }
+
+    // containers that put the identifying bytes after a length or size field
+    match (body.get(..4), body.get(8..12)) {
+      (Some(b"RIFF"), Some(b"WEBP")) => return Some("image/webp"),
+      (Some(b"RIFF"), Some(b"WAVE")) => return Some("audio/wav"),
+      (_, Some(b"avif" | b"avis")) if body.get(4..8) == Some(b"ftyp") => return Some("image/avif"),
+      (_, Some(brand)) if body.get(4..8) == Some(b"ftyp") && Self::MP4_BRANDS.contains(&brand) => return Some("video/mp4"),
+      _ => (),
+    }
+
+    // text is only trusted when it is valid utf-8 with no control bytes
+    // besides whitespace
+    let text = std::str::from_utf8(body).ok()?;
+    if text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+      return None;
+    }
+    let start = text.trim_start().chars().take(64).collect::<String>().to_lowercase();
+    Some(if start.starts_with("<!doctype html") || start.starts_with("<html") {
+      "text/html; charset=utf-8"
+    } else if start.starts_with("<svg") {
+      "image/svg+xml; charset=utf-8"
+    } else if start.starts_with("<?xml") {
+      "application/xml; charset=utf-8"
+    } else {
+      "text/plain; charset=utf-8"
+    })
+  }
+
+  pub fn resolve(path: impl AsRef<Path>, body: &[u8], overrides: &BTreeMap<String, String>) -> String {
+    Self::from_extension(path, overrides)
+      .or_else(|| Self::sniff(body).map(String::from))
+      .unwrap_or_else(|| String::from(Self::DEFAULT))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // an ISO base media file type box with `brand` as its major brand
+  fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+    [&[0, 0, 0, 0x18][..], b"ftyp", brand, &[0, 0, 0, 0], b"isommp42"].concat()
+  }
+
+  fn riff(form: &[u8; 4]) -> Vec<u8> {
+    [&b"RIFF"[..], &[0x24, 0, 0, 0], form, b"fmt "].concat()
+  }
+
+  #[test]
+  fn bmp_needs_a_matching_size_and_zero_reserved_fields() {
+    let mut bmp = [&b"BM"[..], &30u32.to_le_bytes(), &[0; 4]].concat();
+    bmp.resize(30, 0);
+    assert_eq!(HttpMime::sniff(&bmp), Some("image/bmp"));
+
+    assert_eq!(HttpMime::sniff(b"BMW is a car brand"), Some("text/plain; charset=utf-8"));
+    let mut wrong_size = bmp.clone();
+    wrong_size.push(0);
+    assert_ne!(HttpMime::sniff(&wrong_size), Some("image/bmp"));
+    let mut reserved = bmp.clone();
+    reserved[7] = 1;
+    assert_ne!(HttpMime::sniff(&reserved), Some("image/bmp"));
+  }
+
+  #[test]
+  fn mp4_is_told_apart_from_other_iso_brands() {
+    assert_eq!(HttpMime::sniff(&ftyp(b"isom")), Some("video/mp4"));
+    assert_eq!(HttpMime::sniff(&ftyp(b"mp42")), Some("video/mp4"));
+    assert_eq!(HttpMime::sniff(&ftyp(b"avif")), Some("image/avif"));
+    assert_eq!(HttpMime::sniff(&ftyp(b"heic")), None);
+    assert_eq!(HttpMime::sniff(&ftyp(b"mif1")), None);
+  }
+
+  #[test]
+  fn riff_containers_are_told_apart_by_their_form() {
+    assert_eq!(HttpMime::sniff(&riff(b"WEBP")), Some("image/webp"));
+    assert_eq!(HttpMime::sniff(&riff(b"WAVE")), Some("audio/wav"));
+    assert_eq!(HttpMime::sniff(&riff(b"AVI ")), None);
+  }
+
+  #[test]
+  fn text_is_recognised_unless_it_has_control_bytes() {
+    assert_eq!(HttpMime::sniff(b"  <!DOCTYPE html><p>hi</p>"), Some("text/html; charset=utf-8"));
+    assert_eq!(HttpMime::sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Some("image/svg+xml; charset=utf-8"));
+    assert_eq!(HttpMime::sniff(b"<?xml version=\"1.0\"?><a/>"), Some("application/xml; charset=utf-8"));
+    assert_eq!(HttpMime::sniff(b"line one\r\n\tline two\n"), Some("text/plain; charset=utf-8"));
+    assert_eq!(HttpMime::sniff(b"text\x00with a null"), None);
+    assert_eq!(HttpMime::sniff(b"text\x1bwith an escape"), None);
+    assert_eq!(HttpMime::sniff(b"\xff\xfe not utf-8"), None);
+  }
+
+  #[test]
+  fn extensions_and_overrides_win_over_sniffing() {
+    let png = b"\x89PNG\r\n\x1a\n rest of the image";
+    let none = BTreeMap::new();
+    assert_eq!(HttpMime::resolve("image.png", png, &none), "image/png");
+    assert_eq!(HttpMime::resolve("image", png, &none), "image/png");
+    assert_eq!(HttpMime::resolve("notes.txt", png, &none), "text/plain; charset=utf-8");
+    assert_eq!(HttpMime::resolve("STYLE.CSS", b"", &none), "text/css; charset=utf-8");
+    assert_eq!(HttpMime::resolve("blob.unknown", b"\x00\x01\x02", &none), HttpMime::DEFAULT);
+
+    let overrides = BTreeMap::from([(String::from("png"), String::from("image/x-custom")), (String::from("glb"), String::from("model/gltf-binary"))]);
+    assert_eq!(HttpMime::resolve("image.png", png, &overrides), "image/x-custom");
+    assert_eq!(HttpMime::resolve("scene.glb", b"glTF", &overrides), "model/gltf-binary");
+  }
+}
@@ -2,6 +2,7 @@ pub mod error;
 pub mod header;
 pub mod metadata;
 pub mod method;
+pub mod mime;
 pub mod protocol;
 pub mod request;
 pub mod response;
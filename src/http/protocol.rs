@@ -1,3 +1,4 @@
+use std::fmt;
 use std::str::FromStr;
 
 use crate::http::error::HttpError;
@@ -5,18 +6,22 @@ use crate::http::error::HttpError;
 #[derive(Debug, Clone)]
 pub enum HttpProtocol {
   Http10,
-  Http11
+  Http11,
+  Http2
 }
 
-impl ToString for HttpProtocol {
-    fn to_string(&self) -> String {
-        match self {
-          HttpProtocol::Http10 => String::from("HTTP/1.0"),
-          HttpProtocol::Http11 => String::from("HTTP/1.1"),
-        }
+impl fmt::Display for HttpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+          HttpProtocol::Http10 => "HTTP/1.0",
+          HttpProtocol::Http11 => "HTTP/1.1",
+          HttpProtocol::Http2 => "HTTP/2",
+        })
     }
 }
 
+// the versions a 1.x request line may carry; http/2 requests never have one,
+// and are converted from the h2 crate's types instead
 impl FromStr for HttpProtocol {
   type Err = HttpError;
 
@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum HttpStatus {
@@ -74,71 +76,77 @@ pub enum HttpStatus {
   NetworkAuthenticationRequired,  // 511
 }
 
-impl ToString for HttpStatus {
-    fn to_string(&self) -> String {
-        match self {
-          HttpStatus::Continue => String::from("100 Continue"),
-          HttpStatus::SwitchingProtocols => String::from("101 Switching Protocols"),
-          HttpStatus::Processing => String::from("102 Processing"),
-          HttpStatus::EarlyHints => String::from("103 Early Hints"),
-          HttpStatus::Ok => String::from("200 OK"),
-          HttpStatus::Created => String::from("201 Created"),
-          HttpStatus::Accepted => String::from("202 Accepted"),
-          HttpStatus::NonAuthoritativeInformation => String::from("203 Non-Authoritative Information"),
-          HttpStatus::NoContent => String::from("204 No Content"),
-          HttpStatus::ResetContent => String::from("205 Reset Content"),
-          HttpStatus::PartialContent => String::from("206 Partial Content"),
-          HttpStatus::MultiStatus => String::from("207 Multi-Status"),
-          HttpStatus::AlreadyReported => String::from("208 Already Reported"),
-          HttpStatus::ImUsed => String::from("226 IM Used"),
-          HttpStatus::MultipleChoices => String::from("300 Multiple Choices"),
-          HttpStatus::MovedPermanently => String::from("301 Moved Permanently"),
-          HttpStatus::Found => String::from("302 Found"),
-          HttpStatus::SeeOther => String::from("303 See Other"),
-          HttpStatus::NotModified => String::from("304 Not Modified"),
-          HttpStatus::UseProxy => String::from("305 Use Proxy"),
-          HttpStatus::TemporaryRedirect => String::from("307 Temporary Redirect"),
-          HttpStatus::PermanentRedirect => String::from("308 Permanent Redirect"),
-          HttpStatus::BadRequest => String::from("400 Bad Request"),
-          HttpStatus::Unauthorized => String::from("401 Unauthorized"),
-          HttpStatus::PaymentRequired => String::from("402 Payment Required"),
-          HttpStatus::Forbidden => String::from("403 Forbidden"),
-          HttpStatus::NotFound => String::from("404 Not Found"),
-          HttpStatus::MethodNotAllowed => String::from("405 Method Not Allowed"),
-          HttpStatus::NotAcceptable => String::from("406 Not Acceptable"),
-          HttpStatus::ProxyAuthenticationRequired => String::from("407 Proxy Authentication Required"),
-          HttpStatus::RequestTimeout => String::from("408 Request Timeout"),
-          HttpStatus::Conflict => String::from("409 Conflict"),
-          HttpStatus::Gone => String::from("410 Gone"),
-          HttpStatus::LengthRequired => String::from("411 Length Required"),
-          HttpStatus::PreconditionFailed => String::from("412 Precondition Failed"),
-          HttpStatus::ContentTooLarge => String::from("413 Content Too Large"),
-          HttpStatus::URITooLong => String::from("414 URI Too Long"),
-          HttpStatus::UnsupportedMediaType => String::from("415 Unsupported Media Type"),
-          HttpStatus::RangeNotSatisfiable => String::from("416 Range Not Satisfiable"),
-          HttpStatus::ExceptionFailed => String::from("417 Exception Failed"),
-          HttpStatus::ImATeapot => String::from("418 I'm a teapot"),
-          HttpStatus::MisdirectedRequest => String::from("421 Misdirected Request"),
-          HttpStatus::UnprocessableContent => String::from("422 Unprocessable Content"),
-          HttpStatus::Locked => String::from("423 Locked"),
-          HttpStatus::FailedDependency => String::from("424 Failed Dependency"),
-          HttpStatus::TooEarly => String::from("425 Too Early"),
-          HttpStatus::UpgradeRequired => String::from("426 Upgrade Required"),
-          HttpStatus::PreconditionRequired => String::from("428 Precondition Required"),
-          HttpStatus::TooManyRequests => String::from("429 Too Many Requests"),
-          HttpStatus::RequestHeaderFieldsTooLarge => String::from("431 Request Header Fields Too Large"),
-          HttpStatus::UnavailableForLegalReasons => String::from("451 Unavailable For Legal Reasons"),
-          HttpStatus::InternalServerError => String::from("500 Internal Server Error"),
-          HttpStatus::NotImplemented => String::from("501 Not Implemented"),
-          HttpStatus::BadGateway => String::from("502 Bad Gateway"),
-          HttpStatus::ServiceUnavailable => String::from("503 Service Unavailable"),
-          HttpStatus::GatewayTimeout => String::from("504 Gateway Timeout"),
-          HttpStatus::HttpVersionNotSupported => String::from("505 HTTP Version Not Supported"),
-          HttpStatus::VariantAlsoNegotiates => String::from("506 Variant Also Negotiates"),
-          HttpStatus::InsufficientStorage => String::from("507 Insufficient Storage"),
-          HttpStatus::LoopDetected => String::from("508 Loop Detected"),
-          HttpStatus::NotExtended => String::from("510 Not Extended"),
-          HttpStatus::NetworkAuthenticationRequired => String::from("511 Network Authentication Required"),
-        }
+impl HttpStatus {
+  pub fn code(&self) -> u16 {
+    self.to_string()[..3].parse().expect("Every status starts with its code")
+  }
+}
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+          HttpStatus::Continue => "100 Continue",
+          HttpStatus::SwitchingProtocols => "101 Switching Protocols",
+          HttpStatus::Processing => "102 Processing",
+          HttpStatus::EarlyHints => "103 Early Hints",
+          HttpStatus::Ok => "200 OK",
+          HttpStatus::Created => "201 Created",
+          HttpStatus::Accepted => "202 Accepted",
+          HttpStatus::NonAuthoritativeInformation => "203 Non-Authoritative Information",
+          HttpStatus::NoContent => "204 No Content",
+          HttpStatus::ResetContent => "205 Reset Content",
+          HttpStatus::PartialContent => "206 Partial Content",
+          HttpStatus::MultiStatus => "207 Multi-Status",
+          HttpStatus::AlreadyReported => "208 Already Reported",
+          HttpStatus::ImUsed => "226 IM Used",
+          HttpStatus::MultipleChoices => "300 Multiple Choices",
+          HttpStatus::MovedPermanently => "301 Moved Permanently",
+          HttpStatus::Found => "302 Found",
+          HttpStatus::SeeOther => "303 See Other",
+          HttpStatus::NotModified => "304 Not Modified",
+          HttpStatus::UseProxy => "305 Use Proxy",
+          HttpStatus::TemporaryRedirect => "307 Temporary Redirect",
+          HttpStatus::PermanentRedirect => "308 Permanent Redirect",
+          HttpStatus::BadRequest => "400 Bad Request",
+          HttpStatus::Unauthorized => "401 Unauthorized",
+          HttpStatus::PaymentRequired => "402 Payment Required",
+          HttpStatus::Forbidden => "403 Forbidden",
+          HttpStatus::NotFound => "404 Not Found",
+          HttpStatus::MethodNotAllowed => "405 Method Not Allowed",
+          HttpStatus::NotAcceptable => "406 Not Acceptable",
+          HttpStatus::ProxyAuthenticationRequired => "407 Proxy Authentication Required",
+          HttpStatus::RequestTimeout => "408 Request Timeout",
+          HttpStatus::Conflict => "409 Conflict",
+          HttpStatus::Gone => "410 Gone",
+          HttpStatus::LengthRequired => "411 Length Required",
+          HttpStatus::PreconditionFailed => "412 Precondition Failed",
+          HttpStatus::ContentTooLarge => "413 Content Too Large",
+          HttpStatus::URITooLong => "414 URI Too Long",
+          HttpStatus::UnsupportedMediaType => "415 Unsupported Media Type",
+          HttpStatus::RangeNotSatisfiable => "416 Range Not Satisfiable",
+          HttpStatus::ExceptionFailed => "417 Exception Failed",
+          HttpStatus::ImATeapot => "418 I'm a teapot",
+          HttpStatus::MisdirectedRequest => "421 Misdirected Request",
+          HttpStatus::UnprocessableContent => "422 Unprocessable Content",
+          HttpStatus::Locked => "423 Locked",
+          HttpStatus::FailedDependency => "424 Failed Dependency",
+          HttpStatus::TooEarly => "425 Too Early",
+          HttpStatus::UpgradeRequired => "426 Upgrade Required",
+          HttpStatus::PreconditionRequired => "428 Precondition Required",
+          HttpStatus::TooManyRequests => "429 Too Many Requests",
+          HttpStatus::RequestHeaderFieldsTooLarge => "431 Request Header Fields Too Large",
+          HttpStatus::UnavailableForLegalReasons => "451 Unavailable For Legal Reasons",
+          HttpStatus::InternalServerError => "500 Internal Server Error",
+          HttpStatus::NotImplemented => "501 Not Implemented",
+          HttpStatus::BadGateway => "502 Bad Gateway",
+          HttpStatus::ServiceUnavailable => "503 Service Unavailable",
+          HttpStatus::GatewayTimeout => "504 Gateway Timeout",
+          HttpStatus::HttpVersionNotSupported => "505 HTTP Version Not Supported",
+          HttpStatus::VariantAlsoNegotiates => "506 Variant Also Negotiates",
+          HttpStatus::InsufficientStorage => "507 Insufficient Storage",
+          HttpStatus::LoopDetected => "508 Loop Detected",
+          HttpStatus::NotExtended => "510 Not Extended",
+          HttpStatus::NetworkAuthenticationRequired => "511 Network Authentication Required",
+        })
     }
 }
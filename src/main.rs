@@ -1,82 +1,780 @@
+use std::any::Any;
+use std::net::IpAddr;
 use std::path::Path;
-use std::{fs, io, net};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::io::BufRead;
+use std::{io, panic, thread};
 
+use chrono::Local;
+use clap::Parser;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::io::SyncIoBridge;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::cli::{BuildArgs, CheckArgs, Cli, CliCommand, CliStatus, NewKind, ServeArgs};
+
+use crate::http::error::HttpError;
 use crate::http::header::HttpHeader;
+use crate::http::metadata::Metadata;
 use crate::http::method::HttpMethod;
+use crate::http::mime::HttpMime;
+use crate::http::protocol::HttpProtocol;
+use crate::http::request::Request;
 use crate::http::response::Response;
 use crate::http::status::HttpStatus;
 use crate::server::error::ServerError;
+use crate::server::h2::ServerH2;
 use crate::server::io::ServerIo;
+use crate::server::cache::{ServerFileCache, ServerRouterCache};
+use crate::server::limit::ServerLimiter;
+use crate::server::log::ServerLog;
+use crate::server::metrics::ServerMetrics;
+use crate::server::reload::LiveReload;
 use crate::server::router::Router;
+use crate::server::state::ServerState;
+use crate::server::timeout::{ServerDeadline, ServerPhase, ServerTimeoutStream};
+use crate::server::tls::ServerTls;
 use crate::site::builder::SiteBuilder;
+use crate::site::config::{SiteConfig, SiteServerConfig};
+use crate::site::csp::SiteCsp;
+use crate::site::graph::SiteDependencyGraph;
+use crate::site::options::SiteBuildOptions;
+use crate::site::scaffold::SiteScaffold;
+use crate::site::watcher::SiteWatcher;
 
+mod cli;
 mod site;
 mod server;
 mod http;
 
-fn get_headers(path: impl AsRef<Path>) -> Vec<HttpHeader> {
-    match path.as_ref().extension() {
-        Some(extension) => {
-            match extension.to_str() {
-                Some("css") => vec![HttpHeader::new("Content-Type", "text/css")],
-                Some("png") => vec![HttpHeader::new("Content-Type", "image/png")],
-                Some("html") => vec![
-                    HttpHeader::new("Content-Type", "text/html"),
-                    HttpHeader::new("Content-Language", "en-US"),
-                ],
-                _ => vec![]
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+const PREFACE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn get_headers(path: impl AsRef<Path>, body: &[u8], site: &SiteConfig) -> Vec<HttpHeader> {
+    let content_type = HttpMime::resolve(path, body, &site.server.mime_types);
+    if content_type.starts_with("text/html") {
+        vec![
+            HttpHeader::new("Content-Type", &content_type),
+            HttpHeader::new("Content-Language", &site.language),
+        ]
+    } else {
+        vec![HttpHeader::new("Content-Type", &content_type)]
+    }
+}
+
+// the configured policy with the hashes of the page's inline scripts and
+// styles; in watch mode the page is hashed as it is served, since the reload
+// client is injected after the build
+fn get_csp_headers(root: &Path, filepath: &Path, body: &[u8], state: &ServerState) -> Vec<HttpHeader> {
+    let security = &state.site.server.security;
+    if !security.hash_inline || security.content_security_policy.is_empty() || filepath.extension().is_none_or(|e| e != "html") {
+        return vec![];
+    }
+    let sources = match state.reload {
+        Some(_) => SiteCsp::sources(&String::from_utf8_lossy(body)),
+        None => {
+            let file = filepath.strip_prefix(root).unwrap_or(filepath).to_string_lossy();
+            let table = state.files.read(&root.join(SiteCsp::FILE)).map(|(table, _)| table).unwrap_or_default();
+            SiteCsp::read(&String::from_utf8_lossy(&table), &file)
+        }
+    };
+    vec![HttpHeader::new("Content-Security-Policy", &SiteCsp::policy(&security.content_security_policy, &sources))]
+}
+
+fn get_cache_headers(router: &Router, resource: &str) -> Vec<HttpHeader> {
+    if router.is_fingerprinted(resource) {
+        vec![HttpHeader::new("Cache-Control", "public, max-age=31536000, immutable")]
+    } else {
+        vec![]
+    }
+}
+
+// the server name, the security headers and the configured headers go on
+// every response; later entries replace earlier ones of the same name, so a
+// path can override a default. nosniff makes browsers trust the declared
+// content type, and with TLS configured every response either is https or
+// redirects there, so HSTS goes on all of them
+fn with_server_headers(headers: Vec<HttpHeader>, server: &SiteServerConfig, resource: &str) -> Vec<HttpHeader> {
+    let configured = server.headers.iter()
+        .chain(server.paths.iter().filter(|paths| paths.matches(resource)).flat_map(|paths| paths.headers.iter()))
+        .map(|(name, value)| HttpHeader::new(name, value));
+
+    let mut all_headers = vec![
+        HttpHeader::new("Server", &server.name),
+        HttpHeader::new("X-Content-Type-Options", "nosniff"),
+    ];
+    if let Some(tls) = &server.tls {
+        all_headers.push(HttpHeader::new("Strict-Transport-Security", &tls.hsts()));
+    }
+    all_headers.extend(server.security.headers().into_iter().map(|(name, value)| HttpHeader::new(name, value)));
+    for header in headers.into_iter().chain(configured) {
+        all_headers.retain(|existing| !existing.name.eq_ignore_ascii_case(&header.name));
+        all_headers.push(header);
+    }
+    all_headers
+}
+
+fn reload_events_head(protocol: HttpProtocol, server: &SiteServerConfig) -> Response {
+    Response {
+        protocol,
+        status: HttpStatus::Ok,
+        headers: with_server_headers(vec![
+            HttpHeader::new("Content-Type", "text/event-stream"),
+            HttpHeader::new("Cache-Control", "no-cache"),
+        ], server, LiveReload::EVENTS_PATH),
+        body: None,
+    }
+}
+
+// too many 1.1 event streams are open already; the browser gives up on a
+// status other than 200, so a reload of the page is what tries again
+fn reload_events_unavailable(request: &Request, state: &ServerState) -> Response {
+    Response {
+        protocol: request.metadata.protocol.clone(),
+        status: HttpStatus::ServiceUnavailable,
+        headers: with_server_headers(vec![], &state.site.server, &request.metadata.path),
+        body: None,
+    }
+}
+
+fn stream_reload_events(writer: &mut impl io::Write, deadline: &ServerDeadline, reload: &LiveReload, protocol: HttpProtocol, server: &SiteServerConfig) -> Result<(), ServerError> {
+    deadline.start(ServerPhase::Write);
+    ServerIo::write_stream_head(writer, &reload_events_head(protocol, server))?;
+
+    // 1.1 connections already have a blocking thread to themselves
+    let runtime = tokio::runtime::Handle::current();
+    let mut seen = reload.generation();
+    while let Some((generation, event)) = runtime.block_on(reload.next_event(seen)) {
+        deadline.start(ServerPhase::Write);
+        ServerIo::write_chunk(writer, event.as_bytes())?;
+        seen = generation;
+    }
+    Ok(())
+}
+
+fn metrics_response(request: &Request, state: &ServerState) -> Response {
+    Response {
+        protocol: request.metadata.protocol.clone(),
+        status: HttpStatus::Ok,
+        headers: with_server_headers(vec![
+            HttpHeader::new("Content-Type", "text/plain; version=0.0.4; charset=utf-8"),
+            HttpHeader::new("Cache-Control", "no-store"),
+        ], &state.site.server, &request.metadata.path),
+        body: Some(state.metrics.render().into_bytes().into()),
+    }
+}
+
+fn too_many_requests(request: &Request, state: &ServerState, retry: Duration) -> Response {
+    let seconds = ServerLimiter::retry_after(retry);
+    Response {
+        protocol: request.metadata.protocol.clone(),
+        status: HttpStatus::TooManyRequests,
+        headers: with_server_headers(vec![HttpHeader::new("Retry-After", &seconds.to_string())], &state.site.server, &request.metadata.path),
+        body: None,
+    }
+}
+
+fn respond(request: &Request, peer: IpAddr, state: &ServerState) -> Result<Response, ServerError> {
+    let client = state.limiter.client(peer, &request.metadata.headers);
+    if let Err(retry) = state.limiter.check(client, &request.metadata.path) {
+        return Ok(too_many_requests(request, state, retry));
+    }
+
+    let (site, reload) = (&*state.site, state.reload.as_deref());
+    let metrics = &site.server.metrics;
+    if metrics.enabled && metrics.port.is_none() && request.metadata.path == metrics.path {
+        return Ok(metrics_response(request, state));
+    }
+
+    let host = HttpHeader::get_header(&request.metadata.headers, "Host");
+    let Some((root, not_found)) = site.site_root(host.as_deref()) else {
+        return Ok(Response {
+            protocol: request.metadata.protocol.clone(),
+            status: HttpStatus::MisdirectedRequest,
+            headers: with_server_headers(vec![], &site.server, &request.metadata.path),
+            body: None,
+        });
+    };
+
+    let router = match (state.routers.router(root, not_found), reload) {
+        (Ok(router), _) => router,
+        // nothing has been built yet, so all there is to show is the error
+        (Err(_), Some(reload)) if reload.error().is_some() => {
+            let page = reload.error_page();
+            return Ok(Response {
+                protocol: request.metadata.protocol.clone(),
+                status: HttpStatus::InternalServerError,
+                headers: with_server_headers([
+                    vec![HttpHeader::new("Content-Type", "text/html")],
+                    get_csp_headers(root, &root.join(not_found), page.as_bytes(), state),
+                ].concat(), &site.server, &request.metadata.path),
+                body: Some(page.into_bytes().into()),
+            });
+        }
+        (Err(error), _) => return Err(error),
+    };
+
+    Ok(match request.metadata.method {
+        HttpMethod::Get => {
+            let (filepath, is_default) = router.resolve_resource_filepath(&request.metadata.path);
+            let (mut body, cached) = state.files.read(&filepath).map_err(|_| ServerError::FileDoesNotExist)?;
+            state.metrics.cache_lookup(cached);
+            if let Some(reload) = reload && filepath.extension().is_some_and(|e| e == "html") {
+                body = reload.inject(&String::from_utf8_lossy(&body)).into_bytes().into();
+            }
+            Response {
+                protocol: request.metadata.protocol.clone(),
+                status: if is_default { HttpStatus::NotFound } else { HttpStatus::Ok },
+                headers: with_server_headers([
+                    match router.resolve_content_type(&request.metadata.path) {
+                        Some(content_type) => vec![HttpHeader::new("Content-Type", &content_type)],
+                        None => get_headers(&filepath, &body, site),
+                    },
+                    if is_default { vec![] } else { get_cache_headers(&router, &request.metadata.path) },
+                    get_csp_headers(root, &filepath, &body, state),
+                ].concat(), &site.server, &request.metadata.path),
+                body: Some(body),
             }
+        }
+        _ => Response {
+                status: HttpStatus::MethodNotAllowed,
+                protocol: request.metadata.protocol.clone(),
+                headers: with_server_headers(vec![], &site.server, &request.metadata.path),
+                body: None
+            }
+    })
+}
+
+// http/2 streams share the connection, so a failure becomes a 500 on its own
+// stream instead of closing everything
+fn respond_h2(request: &Request, peer: IpAddr, state: &ServerState) -> Response {
+    if state.reload.is_some() && request.metadata.path == LiveReload::EVENTS_PATH {
+        return reload_events_head(HttpProtocol::Http2, &state.site.server);
+    }
+    respond(request, peer, state).unwrap_or_else(|_| Response {
+        protocol: HttpProtocol::Http2,
+        status: HttpStatus::InternalServerError,
+        headers: with_server_headers(vec![], &state.site.server, &request.metadata.path),
+        body: None,
+    })
+}
+
+// reads a request with the head and the body each held to their timeout; a
+// client too slow to send one is answered with a 408, and a request line for
+// a version other than 1.0 or 1.1 with a 505, each recorded against whatever
+// of the request had arrived
+fn read_request(reader: &mut io::BufReader<impl io::Read + io::Write>, deadline: &ServerDeadline, client: IpAddr, state: &ServerState) -> Result<Request, ServerError> {
+    let started = Instant::now();
+    let (metadata, error) = match ServerIo::read_head(reader) {
+        Ok(metadata) => {
+            deadline.start(ServerPhase::Body);
+            match ServerIo::read_body(reader, &metadata) {
+                Ok(body) => return Ok(Request { metadata, body }),
+                Err(error) => (Some(metadata), error),
+            }
+        }
+        Err(error) => (None, error),
+    };
+    let status = match error {
+        ServerError::TimedOut => HttpStatus::RequestTimeout,
+        ServerError::HttpError(HttpError::InvalidProtocol) => HttpStatus::HttpVersionNotSupported,
+        _ => return Err(error),
+    };
+    deadline.start(ServerPhase::Write);
+    let response = Response {
+        protocol: HttpProtocol::Http11,
+        status,
+        headers: with_server_headers(vec![], &state.site.server, ""),
+        body: None,
+    };
+    if ServerIo::write_response(&mut io::BufWriter::new(reader.get_mut()), &response, false).is_ok() {
+        let metadata = metadata.unwrap_or_else(|| Metadata::unread(HttpProtocol::Http11));
+        state.record(client, &metadata, &response, started);
+    }
+    Err(error)
+}
+
+// 1.1 keeps connections open unless asked not to, 1.0 only when asked to, and
+// neither once the server is shutting down
+fn keep_alive(request: &Request, state: &ServerState) -> bool {
+    let connection = HttpHeader::get_header(&request.metadata.headers, "Connection").unwrap_or_default();
+    let has = |option: &str| connection.split(',').any(|value| value.trim().eq_ignore_ascii_case(option));
+    !state.shutdown.is_cancelled() && match request.metadata.protocol {
+        HttpProtocol::Http10 => has("keep-alive"),
+        _ => !has("close"),
+    }
+}
+
+fn handle_connection(stream: impl io::Read + io::Write, deadline: &ServerDeadline, client: IpAddr, state: &ServerState) -> Result<(), ServerError> {
+    let _open = state.metrics.open_connection();
+    let mut reader = io::BufReader::new(stream);
+
+    loop {
+        let request = read_request(&mut reader, deadline, client, state)?;
+        let started = Instant::now();
+        let keep_alive = keep_alive(&request, state);
+        let mut writer = io::BufWriter::new(reader.get_mut());
+
+        let response = match state.reload.as_deref().filter(|_| request.metadata.path == LiveReload::EVENTS_PATH) {
+            Some(reload) => match reload.open_blocking_stream() {
+                Some(_stream) => return stream_reload_events(&mut writer, deadline, reload, request.metadata.protocol, &state.site.server),
+                None => reload_events_unavailable(&request, state),
+            },
+            None => respond(&request, client, state)?,
+        };
+        deadline.start(ServerPhase::Write);
+        ServerIo::write_response(&mut writer, &response, keep_alive)?;
+        drop(writer);
+        state.record(client, &request.metadata, &response, started);
+        if !keep_alive {
+            return Ok(());
+        }
+
+        // the idle timeout covers the wait for the next request to start, and
+        // the head timeout the rest of it
+        deadline.start(ServerPhase::Idle);
+        if reader.fill_buf().map_or(true, |buffer| buffer.is_empty()) {
+            return Ok(());
+        }
+        deadline.start(ServerPhase::Head);
+    }
+}
+
+// over the connection caps, the request is still read so the 429 is a proper
+// answer, and the connection is closed after it
+fn refuse_connection(stream: impl io::Read + io::Write, deadline: &ServerDeadline, client: IpAddr, state: &ServerState) -> Result<(), ServerError> {
+    let mut reader = io::BufReader::new(stream);
+    let request = read_request(&mut reader, deadline, client, state)?;
+    let started = Instant::now();
+    let response = too_many_requests(&request, state, ServerLimiter::CONNECTION_RETRY);
+    deadline.start(ServerPhase::Write);
+    ServerIo::write_response(&mut io::BufWriter::new(reader.get_mut()), &response, false)?;
+    state.record(client, &request.metadata, &response, started);
+    Ok(())
+}
+
+async fn handle_h2_connection(stream: impl AsyncRead + AsyncWrite + Unpin, client: IpAddr, state: ServerState, permitted: bool) {
+    let _open = state.metrics.open_connection();
+    let handler_state = state.clone();
+    let result = if permitted {
+        let handler = Arc::new(move |request: &Request| respond_h2(request, client, &handler_state));
+        ServerH2::serve(stream, client, handler, state.clone()).await
+    } else {
+        // over the connection caps, the streams already sent get a 429 and the
+        // client is sent a GOAWAY straight away
+        let handler = Arc::new(move |request: &Request| too_many_requests(request, &handler_state, ServerLimiter::CONNECTION_RETRY));
+        let closing = ServerState { shutdown: CancellationToken::new(), ..state.clone() };
+        closing.shutdown.cancel();
+        ServerH2::serve(stream, client, handler, closing).await
+    };
+    if let Err(error) = result {
+        state.error(client, &error);
+    }
+}
+
+// 1.1 connections are handled on blocking threads, bridged back to the async
+// stream through a wrapper that holds every phase to its timeout; the handler
+// is awaited so the connection counts as open until it is done, which is what
+// shutdown waits on
+async fn run_blocking<T, F>(stream: T, deadline: Arc<ServerDeadline>, client: IpAddr, state: ServerState, handle: F)
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    F: FnOnce(SyncIoBridge<ServerTimeoutStream<T>>, &ServerDeadline, &ServerState) -> Result<(), ServerError> + Send + 'static,
+{
+    let stream = SyncIoBridge::new(ServerTimeoutStream::new(stream, deadline.clone(), state.shutdown.clone()));
+    let _ = tokio::task::spawn_blocking(move || {
+        match handle(stream, &deadline, &state) {
+            Ok(()) | Err(ServerError::ConnectionClosed) => (),
+            Err(error) => state.error(client, &error),
+        }
+    }).await;
+}
+
+// a plain connection is h2c when it opens with the http/2 preface, which can
+// arrive split across reads, and 1.1 otherwise
+async fn is_h2c(socket: &TcpStream) -> bool {
+    let mut buffer = [0; ServerH2::PREFACE.len()];
+    loop {
+        match socket.peek(&mut buffer).await {
+            Ok(n) if n == buffer.len() => return buffer == ServerH2::PREFACE,
+            Ok(n) if n > 0 && ServerH2::PREFACE.starts_with(&buffer[..n]) => tokio::time::sleep(PREFACE_POLL_INTERVAL).await,
+            _ => return false,
+        }
+    }
+}
+
+fn client_ip(socket: &TcpStream) -> IpAddr {
+    socket.peer_addr().map(|addr| addr.ip()).unwrap_or(IpAddr::from([0, 0, 0, 0]))
+}
+
+async fn handle_plain(socket: TcpStream, state: ServerState) {
+    let client = client_ip(&socket);
+    let permit = state.limiter.open(client);
+    // the wait for the preface counts towards the head timeout; a client that
+    // has not finished it by then is treated as a slow 1.1 client, so it is
+    // answered with a 408
+    let deadline = Arc::new(ServerDeadline::new(&state.site.server.timeouts));
+    let is_h2c = tokio::time::timeout_at(deadline.deadline().into(), is_h2c(&socket)).await.unwrap_or(false);
+    if is_h2c {
+        handle_h2_connection(socket, client, state, permit.is_some()).await;
+    } else {
+        match permit {
+            Some(_) => run_blocking(socket, deadline, client, state, move |stream, deadline, state| handle_connection(stream, deadline, client, state)).await,
+            None => run_blocking(socket, deadline, client, state, move |stream, deadline, state| refuse_connection(stream, deadline, client, state)).await,
+        }
+    }
+}
+
+// the protocol comes from ALPN; 1.1 connections go through the same blocking
+// handler as plain ones
+async fn handle_tls(socket: TcpStream, acceptor: TlsAcceptor, state: ServerState) {
+    let client = client_ip(&socket);
+    let permit = state.limiter.open(client);
+    // the handshake counts towards the head timeout
+    let deadline = Arc::new(ServerDeadline::new(&state.site.server.timeouts));
+    let Ok(Ok(tls_stream)) = tokio::time::timeout_at(deadline.deadline().into(), acceptor.accept(socket)).await else {
+        return;
+    };
+    if tls_stream.get_ref().1.alpn_protocol() == Some(b"h2") {
+        handle_h2_connection(tls_stream, client, state, permit.is_some()).await;
+    } else {
+        match permit {
+            Some(_) => run_blocking(tls_stream, deadline, client, state, move |stream, deadline, state| handle_connection(stream, deadline, client, state)).await,
+            None => run_blocking(tls_stream, deadline, client, state, move |stream, deadline, state| refuse_connection(stream, deadline, client, state)).await,
+        }
+    }
+}
+
+// the plain listener when TLS is on; everything is sent to the same path on
+// the https port
+fn redirect_connection(stream: impl io::Read + io::Write, deadline: &ServerDeadline, client: IpAddr, state: &ServerState, tls_port: u16) -> Result<(), ServerError> {
+    let _open = state.metrics.open_connection();
+    let site = &state.site;
+    let mut reader = io::BufReader::new(stream);
+    let request = read_request(&mut reader, deadline, client, state)?;
+    let started = Instant::now();
+    let host = HttpHeader::get_header(&request.metadata.headers, "Host");
+    let response = match ServerTls::redirect_location(host.as_deref(), &request.metadata.path, tls_port) {
+        Some(location) => Response {
+            protocol: request.metadata.protocol.clone(),
+            status: HttpStatus::PermanentRedirect,
+            headers: with_server_headers(vec![HttpHeader::new("Location", &location)], &site.server, &request.metadata.path),
+            body: None,
         },
-        None => vec![]
+        None => Response {
+            protocol: request.metadata.protocol.clone(),
+            status: HttpStatus::BadRequest,
+            headers: with_server_headers(vec![], &site.server, &request.metadata.path),
+            body: None,
+        },
+    };
+    deadline.start(ServerPhase::Write);
+    ServerIo::write_response(&mut io::BufWriter::new(reader.get_mut()), &response, false)?;
+    state.record(client, &request.metadata, &response, started);
+    Ok(())
+}
+
+// the admin port answers nothing but the metrics
+fn admin_connection(stream: impl io::Read + io::Write, deadline: &ServerDeadline, client: IpAddr, state: &ServerState) -> Result<(), ServerError> {
+    let mut reader = io::BufReader::new(stream);
+    let request = read_request(&mut reader, deadline, client, state)?;
+    let response = if request.metadata.path == state.site.server.metrics.path {
+        metrics_response(&request, state)
+    } else {
+        Response {
+            protocol: request.metadata.protocol.clone(),
+            status: HttpStatus::NotFound,
+            headers: with_server_headers(vec![], &state.site.server, &request.metadata.path),
+            body: None,
+        }
+    };
+    deadline.start(ServerPhase::Write);
+    ServerIo::write_response(&mut io::BufWriter::new(reader.get_mut()), &response, false)
+}
+
+async fn handle_redirect(socket: TcpStream, state: ServerState, tls_port: u16) {
+    let client = client_ip(&socket);
+    let deadline = Arc::new(ServerDeadline::new(&state.site.server.timeouts));
+    match state.limiter.open(client) {
+        Some(_permit) => run_blocking(socket, deadline, client, state, move |stream, deadline, state| redirect_connection(stream, deadline, client, state, tls_port)).await,
+        None => run_blocking(socket, deadline, client, state, move |stream, deadline, state| refuse_connection(stream, deadline, client, state)).await,
+    }
+}
+
+async fn handle_admin(socket: TcpStream, state: ServerState) {
+    let client = client_ip(&socket);
+    let deadline = Arc::new(ServerDeadline::new(&state.site.server.timeouts));
+    run_blocking(socket, deadline, client, state, move |stream, deadline, state| admin_connection(stream, deadline, client, state)).await;
+}
+
+async fn bind(addr: IpAddr, port: u16) -> Result<TcpListener, ExitCode> {
+    TcpListener::bind((addr, port)).await.map_err(|error| {
+        eprintln!("Could not listen on {}:{}: {}", addr, port, error);
+        ExitCode::from(CliStatus::IO_ERROR)
+    })
+}
+
+async fn accept(listener: &TcpListener) -> TcpStream {
+    loop {
+        if let Ok((socket, _)) = listener.accept().await {
+            return socket;
+        }
+    }
+}
+
+// accepts connections until shutdown begins, tracking each one so it can be
+// drained; the listener is dropped on return so new connections are refused
+async fn listen<F>(listener: TcpListener, state: ServerState, handle: impl Fn(TcpStream, ServerState) -> F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        tokio::select! {
+            socket = accept(&listener) => {
+                state.connections.spawn(handle(socket, state.clone()));
+            }
+            _ = state.shutdown.cancelled() => return,
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Could not listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
     }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
 }
 
-fn handle_connection(conn: &mut net::TcpStream, router: Router) -> Result<(), ServerError> {
-    let mut reader = io::BufReader::new(conn.try_clone().expect("Could not clone for reader"));
-    let mut writer = io::BufWriter::new(conn.try_clone().expect("Could not clone for writer"));
+// stops accepting, ends the live reload streams and gives the open
+// connections until the drain timeout to finish
+async fn drain(state: ServerState) -> ExitCode {
+    println!("Shutting down, waiting up to {}s for open connections", state.site.server.drain_timeout);
+    state.shutdown.cancel();
+    if let Some(reload) = &state.reload {
+        reload.close();
+    }
+    state.connections.close();
+    let timeout = Duration::from_secs(state.site.server.drain_timeout);
+    if tokio::time::timeout(timeout, state.connections.wait()).await.is_err() {
+        eprintln!("Closing {} connections that did not finish in time", state.connections.len());
+        // blocking handlers cannot be cancelled, and the runtime would wait on
+        // them when it is dropped, so the process exits without dropping it
+        state.log.flush();
+        std::process::exit(i32::from(CliStatus::TEMPORARY_FAILURE));
+    }
+    state.log.flush();
+    ExitCode::SUCCESS
+}
+
+// panics are how most build errors surface, so they are caught here to keep
+// the server running and show the message in the browser instead
+fn compile(options: &SiteBuildOptions) -> Result<SiteDependencyGraph, String> {
+    match panic::catch_unwind(|| SiteBuilder::compile_site(options)) {
+        Ok(Ok(graph)) => Ok(graph),
+        Ok(Err(error)) => Err(error.to_string()),
+        Err(payload) => Err(panic_message(payload)),
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload.downcast_ref::<String>().cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|message| message.to_string()))
+        .unwrap_or_else(|| String::from("The build panicked"))
+}
 
+fn watch(options: SiteBuildOptions, mut graph: Option<SiteDependencyGraph>, reload: Arc<LiveReload>, routers: Arc<ServerRouterCache>) {
+    let mut watcher = SiteWatcher::new(&options.site.directories.content);
     loop {
-        let request = ServerIo::read_request(&mut reader)?;
-
-        let response = match request.metadata.method {
-            HttpMethod::Get => {
-                let (filepath, is_default) = router.resolve_resource_filepath(&request.metadata.path);
-                Response {
-                    protocol: request.metadata.protocol,
-                    status: if is_default { HttpStatus::NotFound } else { HttpStatus::Ok },
-                    headers: get_headers(&filepath),
-                    body: Some(fs::read(&filepath).map_err(|_| ServerError::FileDoesNotExist)?.into()),
-                }
+        thread::sleep(WATCH_INTERVAL);
+        let changes = watcher.changes();
+        if changes.is_empty() {
+            continue;
+        }
+
+        for path in &changes {
+            let affected = graph.as_ref().map(|graph| graph.dependents(path).len()).unwrap_or_default();
+            println!("Changed {} ({} dependent files)", path.display(), affected);
+        }
+
+        match compile(&options) {
+            Ok(new_graph) => {
+                graph = Some(new_graph);
+                routers.clear();
+                reload.notify(None);
+            }
+            Err(error) => {
+                eprintln!("Build failed: {}", error);
+                reload.notify(Some(error));
             }
-            _ => Response {
-                    status: HttpStatus::MethodNotAllowed,
-                    protocol: request.metadata.protocol,
-                    headers: vec![],
-                    body: None
-                }
+        }
+    }
+}
+
+async fn serve(args: ServeArgs, site: SiteConfig) -> ExitCode {
+    let options = args.options(site);
+    let site = Arc::new(options.site.clone());
+    let routers = Arc::new(ServerRouterCache::default());
+    let reload = if args.watch {
+        let reload = Arc::new(LiveReload::default());
+        let graph = compile(&options)
+            .inspect_err(|error| {
+                eprintln!("Build failed: {}", error);
+                reload.notify(Some(error.clone()));
+            })
+            .ok();
+        let (watcher_reload, watcher_routers) = (reload.clone(), routers.clone());
+        thread::spawn(move || watch(options, graph, watcher_reload, watcher_routers));
+        Some(reload)
+    } else if args.no_build {
+        None
+    } else {
+        if let Err(error) = compile(&options) {
+            eprintln!("Build failed: {}", error);
+            return ExitCode::from(CliStatus::CONTENT_ERROR);
+        }
+        None
+    };
+
+    let (addr, port) = (site.server.addr, site.server.port);
+    let listener = match bind(addr, port).await {
+        Ok(listener) => listener,
+        Err(status) => return status,
+    };
+
+    let log = match ServerLog::new(&site.server.log) {
+        Ok(log) => Arc::new(log),
+        Err(error) => {
+            eprintln!("Could not open the access log: {}", error);
+            return ExitCode::from(CliStatus::CANNOT_CREATE);
+        }
+    };
+    let state = ServerState {
+        site: site.clone(),
+        reload,
+        log,
+        metrics: Arc::new(ServerMetrics::default()),
+        files: Arc::new(ServerFileCache::default()),
+        routers,
+        limiter: Arc::new(ServerLimiter::new(&site.server.limits)),
+        shutdown: CancellationToken::new(),
+        connections: TaskTracker::new(),
+    };
+
+    if let Some(metrics_port) = site.server.metrics.port.filter(|_| site.server.metrics.enabled) {
+        let admin_listener = match bind(addr, metrics_port).await {
+            Ok(listener) => listener,
+            Err(status) => return status,
         };
+        println!("Serving metrics on http://{}:{}{}", addr, metrics_port, site.server.metrics.path);
+        tokio::spawn(listen(admin_listener, state.clone(), handle_admin));
+    }
+
+    let Some(tls) = &site.server.tls else {
+        println!("Serving {} on http://{}:{}", site.directories.output.display(), addr, port);
+        tokio::spawn(listen(listener, state.clone(), handle_plain));
+        shutdown_signal().await;
+        return drain(state).await;
+    };
+
+    let acceptor = match ServerTls::new(&tls.cert, &tls.key) {
+        Ok(server_tls) => TlsAcceptor::from(server_tls.server_config()),
+        Err(error) => {
+            eprintln!("Could not load TLS certificate: {}", error);
+            return ExitCode::from(CliStatus::CONFIG_ERROR);
+        }
+    };
+    let tls_listener = match bind(addr, tls.port).await {
+        Ok(listener) => listener,
+        Err(status) => return status,
+    };
+    println!("Serving {} on https://{}:{} and redirecting http://{}:{}", site.directories.output.display(), addr, tls.port, addr, port);
 
-        ServerIo::write_response(&mut writer, &response)?;
+    let tls_port = tls.port;
+    tokio::spawn(listen(listener, state.clone(), move |socket, state| handle_redirect(socket, state, tls_port)));
+    tokio::spawn(listen(tls_listener, state.clone(), move |socket, state| handle_tls(socket, acceptor.clone(), state)));
+    shutdown_signal().await;
+    drain(state).await
+}
 
-        // match HttpHeader::get_header(&request.metadata.headers, "Connection").as_deref() {
-        //     Some("keep-alive") => (),
-        //     _ => { return Ok(()) }
-        // }
-        return Ok(())
+fn build(args: BuildArgs, site: SiteConfig) -> ExitCode {
+    match compile(&args.options(site)) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Build failed: {}", error);
+            ExitCode::from(CliStatus::CONTENT_ERROR)
+        }
+    }
+}
+
+fn check(args: CheckArgs, site: SiteConfig) -> ExitCode {
+    let options = args.options(site);
+    let problems = match panic::catch_unwind(|| SiteBuilder::check_site(&options)) {
+        Ok(Ok(problems)) => problems,
+        Ok(Err(error)) => vec![error.to_string()],
+        Err(payload) => vec![panic_message(payload)],
     };
+
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("No problems found");
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("{} problem(s) found", problems.len());
+        ExitCode::from(CliStatus::CONTENT_ERROR)
+    }
 }
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    SiteBuilder::compile_site().expect("Error compiling site!");
+fn new_post(title: &str, site: SiteConfig) -> ExitCode {
+    match SiteScaffold::new_post(title, Local::now().date_naive(), &site.directories, &site.build.date_format) {
+        Ok(path) => {
+            println!("Created draft {}", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            eprintln!("{}", error);
+            ExitCode::from(CliStatus::CANNOT_CREATE)
+        }
+        Err(error) if error.kind() == io::ErrorKind::InvalidInput => {
+            eprintln!("{}", error);
+            ExitCode::from(CliStatus::CONTENT_ERROR)
+        }
+        Err(error) => {
+            eprintln!("Could not create post: {}", error);
+            ExitCode::from(CliStatus::IO_ERROR)
+        }
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await?;
-    loop {
-        let (socket, _) = listener.accept().await?;
-        let router = Router::from_file("dist", "dist/routes.txt", "404.html").unwrap();
-        let mut tcp_stream = socket.into_std()?;
-        if handle_connection(&mut tcp_stream, router).is_err() {
-            println!("Error with connection.");
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let site = match SiteConfig::read(&cli.config) {
+        Ok(site) => site,
+        Err(error) => {
+            eprintln!("Could not read site configuration: {}", error);
+            return ExitCode::from(CliStatus::CONFIG_ERROR);
         }
+    };
+
+    match cli.command {
+        Some(CliCommand::Build(args)) => build(args, site),
+        Some(CliCommand::Serve(args)) => serve(args, site).await,
+        Some(CliCommand::Check(args)) => check(args, site),
+        Some(CliCommand::New { kind: NewKind::Post { title } }) => new_post(&title, site),
+        None => serve(ServeArgs::default(), site).await,
     }
 }
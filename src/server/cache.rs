@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::server::error::ServerError;
+use crate::server::router::Router;
+
+#[derive(Debug)]
+struct ServerCachedFile {
+  // modification time and size
+  stamp: (SystemTime, u64),
+  contents: Arc<[u8]>,
+}
+
+#[derive(Debug, Default)]
+struct ServerFileCacheState {
+  files: HashMap<PathBuf, ServerCachedFile>,
+  bytes: usize,
+}
+
+// keeps file contents in memory between requests; an entry is only used while
+// the file's modification time and size are unchanged, so rebuilds are picked up at once
+#[derive(Debug, Default)]
+pub struct ServerFileCache {
+  state: Mutex<ServerFileCacheState>,
+}
+
+impl ServerFileCache {
+  const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+  // returns the contents and whether they came from the cache
+  pub fn read(&self, path: &Path) -> io::Result<(Arc<[u8]>, bool)> {
+    let metadata = fs::metadata(path)?;
+    let stamp = (metadata.modified()?, metadata.len());
+    let mut state = self.state.lock().expect("File cache is poisoned");
+    if let Some(cached) = state.files.get(path) && cached.stamp == stamp {
+      return Ok((cached.contents.clone(), true));
+    }
+
+    let contents: Arc<[u8]> = fs::read(path)?.into();
+    // simpler than tracking use, and a site this size fits many times over
+    if state.bytes + contents.len() > Self::MAX_BYTES {
+      state.files.clear();
+      state.bytes = 0;
+    }
+    if let Some(replaced) = state.files.insert(path.to_path_buf(), ServerCachedFile { stamp, contents: contents.clone() }) {
+      state.bytes -= replaced.contents.len();
+    }
+    state.bytes += contents.len();
+    Ok((contents, false))
+  }
+}
+
+#[derive(Debug)]
+struct ServerCachedRouter {
+  // modification time and size of the route file
+  stamp: (SystemTime, u64),
+  router: Arc<Router>,
+}
+
+// parsed route tables per site root, so a request does not read and parse the
+// route file; an entry is dropped when the route file changes, and the watcher
+// clears them all after each rebuild
+#[derive(Debug, Default)]
+pub struct ServerRouterCache {
+  routers: Mutex<HashMap<PathBuf, ServerCachedRouter>>,
+}
+
+impl ServerRouterCache {
+  pub fn router(&self, root: &Path, not_found: &str) -> Result<Arc<Router>, ServerError> {
+    let routes = root.join("routes.txt");
+    let stamp = fs::metadata(&routes)
+      .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+      .map_err(|_| ServerError::RouteFileDoesNotExist)?;
+    let mut routers = self.routers.lock().expect("Router cache is poisoned");
+    if let Some(cached) = routers.get(root) && cached.stamp == stamp {
+      return Ok(cached.router.clone());
+    }
+
+    let router = Arc::new(Router::from_file(&root.to_string_lossy(), &routes.to_string_lossy(), not_found)?);
+    routers.insert(root.to_path_buf(), ServerCachedRouter { stamp, router: router.clone() });
+    Ok(router)
+  }
+
+  pub fn clear(&self) {
+    self.routers.lock().expect("Router cache is poisoned").clear();
+  }
+}
@@ -9,6 +9,10 @@ pub enum ServerError {
   RouteFileDoesNotExist,
   FileDoesNotExist,
   CouldNotReadFromTcpStream,
+  // the client hung up before sending anything, which is not an error
+  ConnectionClosed,
+  CouldNotWriteToTcpStream,
+  TimedOut,
 }
 
 impl From<HttpError> for ServerError {
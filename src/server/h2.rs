@@ -0,0 +1,200 @@
+use std::future::poll_fn;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use chrono::prelude::*;
+use h2::RecvStream;
+use h2::SendStream;
+use h2::server::SendResponse;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::task::TaskTracker;
+
+use crate::http::error::HttpError;
+use crate::http::header::HttpHeader;
+use crate::http::metadata::Metadata;
+use crate::http::method::HttpMethod;
+use crate::http::protocol::HttpProtocol;
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::http::status::HttpStatus;
+use crate::server::error::ServerError;
+use crate::server::reload::LiveReload;
+use crate::server::state::ServerState;
+
+// http/2 framing, HPACK and flow control come from the h2 crate; requests are
+// translated into the same Request and Response types the 1.1 path uses, so
+// both go through one handler
+pub struct ServerH2;
+impl ServerH2 {
+  // sent first by clients speaking h2c with prior knowledge
+  pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+  // streams a client may have open at once on one connection
+  const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+  // headers that only mean something for a single 1.1 connection
+  const CONNECTION_HEADERS: &[&str] = &["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+  fn request(request: &http::Request<RecvStream>) -> Result<Request, HttpError> {
+    let mut headers: Vec<HttpHeader> = request.headers()
+      .iter()
+      .filter_map(|(name, value)| Some(HttpHeader::new(name.as_str(), value.to_str().ok()?)))
+      .collect();
+    if let Some(authority) = request.uri().authority() && HttpHeader::get_header(&headers, "Host").is_none() {
+      headers.push(HttpHeader::new("Host", authority.as_str()));
+    }
+
+    let metadata = Metadata {
+      method: HttpMethod::from_str(request.method().as_str())?,
+      path: request.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/").to_string(),
+      protocol: HttpProtocol::Http2,
+      headers,
+    };
+    metadata.validate()?;
+
+    Ok(Request { metadata, body: None })
+  }
+
+  fn head(response: &Response, content_length: Option<usize>) -> http::Response<()> {
+    let mut head = http::Response::builder().status(response.status.code());
+    for header in &response.headers {
+      if !Self::CONNECTION_HEADERS.contains(&header.name.to_lowercase().as_str()) {
+        head = head.header(header.name.as_str(), header.value.as_str());
+      }
+    }
+    head = head.header("date", Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    if let Some(content_length) = content_length {
+      head = head.header("content-length", content_length);
+    }
+    head.body(()).unwrap_or_else(|_| {
+      http::Response::builder().status(HttpStatus::InternalServerError.code()).body(()).expect("A bare status is a valid response")
+    })
+  }
+
+  // sends as much as the peer's flow control window allows at a time
+  async fn send_data(stream: &mut SendStream<Bytes>, mut data: Bytes, end_of_stream: bool) -> Result<(), ServerError> {
+    while !data.is_empty() {
+      stream.reserve_capacity(data.len());
+      let capacity = match poll_fn(|cx| stream.poll_capacity(cx)).await {
+        Some(Ok(capacity)) => capacity,
+        _ => return Err(ServerError::CouldNotWriteToTcpStream),
+      };
+      let chunk = data.split_to(capacity.min(data.len()));
+      stream.send_data(chunk, end_of_stream && data.is_empty()).map_err(|_| ServerError::CouldNotWriteToTcpStream)?;
+    }
+    Ok(())
+  }
+
+  async fn stream_reload_events(mut stream: SendStream<Bytes>, reload: Arc<LiveReload>) -> Result<(), ServerError> {
+    let mut seen = reload.generation();
+    loop {
+      let Some((generation, event)) = reload.next_event(seen).await else {
+        return stream.send_data(Bytes::new(), true).map_err(|_| ServerError::CouldNotWriteToTcpStream);
+      };
+      Self::send_data(&mut stream, Bytes::from(event), false).await?;
+      seen = generation;
+    }
+  }
+
+  async fn respond<F>(request: http::Request<RecvStream>, mut respond: SendResponse<Bytes>, handler: Arc<F>, client: IpAddr, state: &ServerState) -> Result<(), ServerError>
+  where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+  {
+    let started = Instant::now();
+    let request = match Self::request(&request) {
+      Ok(request) => request,
+      Err(_) => {
+        let response = Response { protocol: HttpProtocol::Http2, status: HttpStatus::BadRequest, headers: vec![], body: None };
+        respond.send_response(Self::head(&response, Some(0)), true).map_err(|_| ServerError::CouldNotWriteToTcpStream)?;
+        state.record(client, &Metadata::unread(HttpProtocol::Http2), &response, started);
+        return Ok(());
+      }
+    };
+
+    let is_event_stream = state.reload.is_some() && request.metadata.path == LiveReload::EVENTS_PATH;
+    // file reads and the router are blocking, like on the 1.1 path
+    let (request, response) = tokio::task::spawn_blocking(move || {
+      let response = handler(&request);
+      (request, response)
+    })
+      .await
+      .map_err(|_| ServerError::CouldNotWriteToTcpStream)?;
+
+    if let Some(reload) = state.reload.clone().filter(|_| is_event_stream) {
+      let stream = respond.send_response(Self::head(&response, None), false).map_err(|_| ServerError::CouldNotWriteToTcpStream)?;
+      return Self::stream_reload_events(stream, reload).await;
+    }
+
+    let body = response.body.as_deref().map(Bytes::copy_from_slice).unwrap_or_default();
+    let mut stream = respond.send_response(Self::head(&response, Some(body.len())), body.is_empty())
+      .map_err(|_| ServerError::CouldNotWriteToTcpStream)?;
+    Self::send_data(&mut stream, body, true).await?;
+    state.record(client, &request.metadata, &response, started);
+    Ok(())
+  }
+
+  // every stream gets its own task, so the handful of assets a page pulls in
+  // are served side by side over the one connection
+  pub async fn serve<T, F>(io: T, client: IpAddr, handler: Arc<F>, state: ServerState) -> Result<(), ServerError>
+  where
+    T: AsyncRead + AsyncWrite + Unpin,
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+  {
+    // the client's settings are held to the head timeout, like a 1.1 head
+    let header_timeout = Duration::from_secs(state.site.server.timeouts.header);
+    let handshake = h2::server::Builder::new()
+      .max_concurrent_streams(Self::MAX_CONCURRENT_STREAMS)
+      .handshake(io);
+    let mut connection = tokio::time::timeout(header_timeout, handshake)
+      .await
+      .map_err(|_| ServerError::TimedOut)?
+      .map_err(|_| ServerError::CouldNotReadFromTcpStream)?;
+
+    // closed from the start, so waiting on it only waits for the streams
+    // open at the time
+    let streams = TaskTracker::new();
+    streams.close();
+    let idle_timeout = Duration::from_secs(state.site.server.timeouts.idle);
+    let mut shutting_down = false;
+    loop {
+      // on shutdown, or once no stream has been open for the idle timeout,
+      // the client is sent a GOAWAY and the connection closes once the streams
+      // already open are done; a client that still holds it open after
+      // another idle timeout is dropped
+      let idle = async {
+        streams.wait().await;
+        tokio::time::sleep(idle_timeout).await;
+      };
+      let accepted = tokio::select! {
+        accepted = connection.accept() => accepted,
+        _ = state.shutdown.cancelled(), if !shutting_down => {
+          connection.graceful_shutdown();
+          shutting_down = true;
+          continue;
+        }
+        _ = idle => {
+          if shutting_down {
+            break;
+          }
+          connection.graceful_shutdown();
+          shutting_down = true;
+          continue;
+        }
+      };
+      let Some(accepted) = accepted else {
+        break;
+      };
+      let (request, respond) = accepted.map_err(|_| ServerError::CouldNotReadFromTcpStream)?;
+      let (handler, state) = (handler.clone(), state.clone());
+      streams.spawn(async move {
+        if let Err(error) = Self::respond(request, respond, handler, client, &state).await {
+          state.error(client, &error);
+        }
+      });
+    }
+    Ok(())
+  }
+}
@@ -1,10 +1,7 @@
 use std::io::BufRead;
-use std::io::BufReader;
-use std::io::BufWriter;
 use std::io::Read;
 use std::io::Write;
-use std::net::TcpStream;
-use std::rc::Rc;
+use std::sync::Arc;
 use std::str::FromStr;
 use chrono::prelude::*;
 
@@ -13,29 +10,42 @@ use crate::http::header::HttpHeader;
 use crate::http::metadata::Metadata;
 use crate::http::method::HttpMethod;
 use crate::http::protocol::HttpProtocol;
-use crate::http::request::Request;
 use crate::http::response::Response;
 use crate::server::error::ServerError;
 
 pub struct ServerIo;
 impl ServerIo {
+  fn read_error(error: std::io::Error) -> ServerError {
+    match error.kind() {
+      std::io::ErrorKind::TimedOut => ServerError::TimedOut,
+      _ => ServerError::CouldNotReadFromTcpStream,
+    }
+  }
+
   // from https://stackoverflow.com/a/30413877
-  fn read_n<R>(reader: R, bytes_to_read: u64) -> Vec<u8>
+  fn read_n<R>(reader: R, bytes_to_read: u64) -> Result<Vec<u8>, ServerError>
   where
       R: Read,
   {
       let mut buf = vec![];
       let mut chunk = reader.take(bytes_to_read);
-      let n = chunk.read_to_end(&mut buf).expect("Didn't read enough");
-      assert_eq!(bytes_to_read as usize, n);
-      buf
+      let n = chunk.read_to_end(&mut buf).map_err(Self::read_error)?;
+      if bytes_to_read as usize != n {
+        return Err(ServerError::CouldNotReadFromTcpStream);
+      }
+      Ok(buf)
   }
 
-  pub fn read_request(reader: &mut BufReader<TcpStream>) -> Result<Request, ServerError> {
+  pub fn read_head(reader: &mut impl BufRead) -> Result<Metadata, ServerError> {
     let mut metadata_bytes: Vec<u8> = Vec::new();
     loop {
-        if reader.read_until(b'\n', &mut metadata_bytes).is_err() {
-          return Err(ServerError::CouldNotReadFromTcpStream)
+        // nothing read means the client hung up, before the request or partway
+        // through it
+        match reader.read_until(b'\n', &mut metadata_bytes) {
+          Ok(0) if metadata_bytes.is_empty() => return Err(ServerError::ConnectionClosed),
+          Ok(0) => return Err(ServerError::CouldNotReadFromTcpStream),
+          Err(error) => return Err(Self::read_error(error)),
+          Ok(_) => (),
         }
         if metadata_bytes.ends_with("\r\n\r\n".as_bytes()) {
             break;
@@ -50,13 +60,13 @@ impl ServerIo {
       .filter(|header_pices| header_pices.len() == 2)
       .map(|header_pieces| {
         HttpHeader::new(
-          header_pieces.get(0).expect("Header does not contain name"),
+          header_pieces.first().expect("Header does not contain name"),
           header_pieces.get(1).expect("Header does not contain value")
         )
       })
       .collect();
 
-    let start_line_parts: Vec<&str> = metadata_lines.get(0).expect("No start line provided").split(" ").collect();
+    let start_line_parts: Vec<&str> = metadata_lines.first().expect("No start line provided").split(" ").collect();
     let metadata = match start_line_parts[..] {
       [method_str, path, protocol] => Ok(Metadata {
           method: HttpMethod::from_str(method_str)?,
@@ -70,31 +80,35 @@ impl ServerIo {
 
     metadata.validate()?;
 
+    Ok(metadata)
+  }
+
+  pub fn read_body(reader: &mut impl BufRead, metadata: &Metadata) -> Result<Option<String>, ServerError> {
     let content_length = HttpHeader::get_header(&metadata.headers, "Content-Length")
           .unwrap_or(String::from("0"))
           .parse::<u64>()
           .unwrap_or(0);
-    let data = Self::read_n(reader, content_length);
+    let data = Self::read_n(reader, content_length)?;
     let body_string = String::from_utf8_lossy(&data);
 
-    Ok(Request {
-      metadata,
-      body: if content_length == 0 { None } else { Some(String::from(body_string)) },
-    })
+    Ok(if content_length == 0 { None } else { Some(String::from(body_string)) })
   }
 
-  pub fn write_response(writer: &mut BufWriter<TcpStream>, response: &Response) -> Result<(), ServerError> {
+
+  pub fn write_response(writer: &mut impl Write, response: &Response, keep_alive: bool) -> Result<(), ServerError> {
     let mut all_headers = response.headers.clone();
     all_headers.append(&mut vec![
-      HttpHeader::new("Server", "Christian's Content Server v0.1"),
       HttpHeader::new(
         "Date",
         &Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
       ),
       HttpHeader::new(
         "Content-Length", 
-        &response.body.clone().unwrap_or(Rc::new([])).len().to_string()
-      )
+        &response.body.clone().unwrap_or(Arc::new([])).len().to_string()
+      ),
+      // clients are told up front when the connection closes after this, so
+      // they do not try to reuse it
+      HttpHeader::new("Connection", if keep_alive { "keep-alive" } else { "close" }),
     ]);
 
     let message = Response {
@@ -104,9 +118,26 @@ impl ServerIo {
       body: response.body.clone()
     }.format();
 
-    writer.write_all(&message).expect("Could not write");
-    writer.flush().expect("Could not flush writer");
+    Self::write_chunk(writer, &message)
+  }
+
+  // writes the status line and headers of a response whose body is streamed
+  // afterwards, so there is no Content-Length
+  pub fn write_stream_head(writer: &mut impl Write, response: &Response) -> Result<(), ServerError> {
+    let mut headers = response.headers.clone();
+    headers.push(HttpHeader::new("Connection", "close"));
+    let message = Response {
+      protocol: response.protocol.clone(),
+      status: response.status.clone(),
+      headers,
+      body: None
+    }.format();
+
+    Self::write_chunk(writer, &message)
+  }
 
-    Ok(())
+  pub fn write_chunk(writer: &mut impl Write, bytes: &[u8]) -> Result<(), ServerError> {
+    writer.write_all(bytes).map_err(|_| ServerError::CouldNotWriteToTcpStream)?;
+    writer.flush().map_err(|_| ServerError::CouldNotWriteToTcpStream)
   }
 }
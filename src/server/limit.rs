@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::http::header::HttpHeader;
+use crate::site::config::{SiteLimitsConfig, SiteRateLimit};
+
+#[derive(Debug)]
+struct ServerBucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+#[derive(Debug, Default)]
+struct ServerConnectionCounts {
+  total: usize,
+  per_ip: HashMap<IpAddr, usize>,
+}
+
+// holds a connection's place under the caps until it is dropped
+pub struct ServerConnectionPermit {
+  limiter: Arc<ServerLimiter>,
+  client: Option<IpAddr>,
+}
+
+impl Drop for ServerConnectionPermit {
+  fn drop(&mut self) {
+    let mut counts = self.limiter.connections.lock().expect("Connection counts are poisoned");
+    counts.total -= 1;
+    if let Some(client) = self.client && let Some(count) = counts.per_ip.get_mut(&client) {
+      *count -= 1;
+      if *count == 0 {
+        counts.per_ip.remove(&client);
+      }
+    }
+  }
+}
+
+// token buckets per client and route prefix, plus the global and per client
+// connection caps
+#[derive(Debug)]
+pub struct ServerLimiter {
+  config: SiteLimitsConfig,
+  buckets: Mutex<HashMap<(IpAddr, usize), ServerBucket>>,
+  connections: Mutex<ServerConnectionCounts>,
+}
+
+impl ServerLimiter {
+  // how long a client turned away at the connection caps is asked to wait
+  pub const CONNECTION_RETRY: Duration = Duration::from_secs(1);
+  // past this many buckets some are dropped to make room, see `evict`
+  const MAX_BUCKETS: usize = 10_000;
+
+  pub fn new(config: &SiteLimitsConfig) -> ServerLimiter {
+    ServerLimiter {
+      config: config.clone(),
+      buckets: Mutex::new(HashMap::new()),
+      connections: Mutex::new(ServerConnectionCounts::default()),
+    }
+  }
+
+  fn is_trusted(&self, peer: IpAddr) -> bool {
+    self.config.trusted_proxies.contains(&peer)
+  }
+
+  // takes a place for a connection from `peer`, or None when either cap is
+  // reached; trusted proxies carry many clients, so only the global cap
+  // applies to them
+  pub fn open(self: &Arc<Self>, peer: IpAddr) -> Option<ServerConnectionPermit> {
+    let mut counts = self.connections.lock().expect("Connection counts are poisoned");
+    if counts.total >= self.config.max_connections {
+      return None;
+    }
+    let client = (!self.is_trusted(peer)).then_some(peer);
+    if let Some(client) = client {
+      let count = counts.per_ip.entry(client).or_default();
+      if *count >= self.config.max_connections_per_ip {
+        return None;
+      }
+      *count += 1;
+    }
+    counts.total += 1;
+    Some(ServerConnectionPermit { limiter: self.clone(), client })
+  }
+
+  // an address from X-Forwarded-For or a Forwarded for= parameter, which may
+  // be quoted, bracketed and carry a port
+  fn forwarded_address(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(rest) = value.strip_prefix('[') {
+      return rest.split(']').next()?.parse().ok();
+    }
+    value.parse().ok().or_else(|| value.rsplit_once(':')?.0.parse().ok())
+  }
+
+  fn forwarded_chain(headers: &[HttpHeader]) -> Vec<String> {
+    if let Some(forwarded) = HttpHeader::get_header(headers, "Forwarded") {
+      return forwarded.split(',')
+        .filter_map(|element| {
+          element.split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+            .map(|(_, value)| value.to_string())
+        })
+        .collect();
+    }
+    HttpHeader::get_header(headers, "X-Forwarded-For")
+      .map(|forwarded| forwarded.split(',').map(String::from).collect())
+      .unwrap_or_default()
+  }
+
+  // the client a request is from: the peer itself, or behind trusted proxies
+  // the last address in the forwarded chain that is not one of them, since
+  // anything before that could have been made up by the client
+  pub fn client(&self, peer: IpAddr, headers: &[HttpHeader]) -> IpAddr {
+    if !self.is_trusted(peer) {
+      return peer;
+    }
+    let mut client = peer;
+    for hop in Self::forwarded_chain(headers).iter().rev() {
+      match Self::forwarded_address(hop) {
+        Some(address) => {
+          client = address;
+          if !self.is_trusted(address) {
+            break;
+          }
+        }
+        // obfuscated and unknown hops cannot be looked past
+        None => break,
+      }
+    }
+    client
+  }
+
+  // the whole seconds a client is told to wait in Retry-After, rounded up so
+  // it does not come back too early
+  pub fn retry_after(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+  }
+
+  // the longest prefix wins, so a stricter limit can sit under a general one
+  fn route(&self, path: &str) -> Option<(usize, &SiteRateLimit)> {
+    self.config.routes.iter()
+      .enumerate()
+      .filter(|(_, limit)| path.starts_with(&limit.prefix))
+      .max_by_key(|(_, limit)| limit.prefix.len())
+  }
+
+  // drops the buckets that have refilled, since a full bucket is the same as
+  // no bucket, and then the least recently used ones until a quarter of the
+  // room is free; a client rotating through addresses leaves none of its
+  // buckets full, and freeing a quarter at a time spreads the cost of a sweep
+  // over many new clients
+  fn evict(buckets: &mut HashMap<(IpAddr, usize), ServerBucket>, routes: &[SiteRateLimit], now: Instant) {
+    buckets.retain(|(_, index), bucket| {
+      let limit = &routes[*index];
+      bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * limit.rate < f64::from(limit.burst)
+    });
+    let target = Self::MAX_BUCKETS / 4 * 3;
+    if buckets.len() > target {
+      let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+      let excess = updated.len() - target;
+      let (_, cutoff, _) = updated.select_nth_unstable(excess - 1);
+      let cutoff = *cutoff;
+      buckets.retain(|_, bucket| bucket.updated > cutoff);
+    }
+  }
+
+  // takes a token for a request to `path`, or returns how long until one is
+  // available
+  pub fn check(&self, client: IpAddr, path: &str) -> Result<(), Duration> {
+    let Some((index, limit)) = self.route(path) else {
+      return Ok(());
+    };
+    let now = Instant::now();
+    let burst = f64::from(limit.burst);
+    let mut buckets = self.buckets.lock().expect("Rate limit buckets are poisoned");
+    if buckets.len() >= Self::MAX_BUCKETS && !buckets.contains_key(&(client, index)) {
+      Self::evict(&mut buckets, &self.config.routes, now);
+    }
+
+    let bucket = buckets.entry((client, index)).or_insert(ServerBucket { tokens: burst, updated: now });
+    bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * limit.rate).min(burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      Ok(())
+    } else {
+      Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn limiter(config: SiteLimitsConfig) -> Arc<ServerLimiter> {
+    Arc::new(ServerLimiter::new(&config))
+  }
+
+  fn rate_limited(rate: f64, burst: u32) -> Arc<ServerLimiter> {
+    limiter(SiteLimitsConfig {
+      routes: vec![SiteRateLimit { prefix: String::from("/api"), rate, burst }],
+      ..SiteLimitsConfig::default()
+    })
+  }
+
+  fn ip(address: &str) -> IpAddr {
+    address.parse().expect("Not an address")
+  }
+
+  #[test]
+  fn tokens_run_out_and_refill_at_the_rate() {
+    let limiter = rate_limited(2.0, 2);
+    let client = ip("192.0.2.1");
+    assert!(limiter.check(client, "/api/a").is_ok());
+    assert!(limiter.check(client, "/api/b").is_ok());
+    let wait = limiter.check(client, "/api/c").expect_err("The bucket should be empty");
+    assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500), "waited {:?}", wait);
+
+    // other clients and paths outside the prefix are not affected
+    assert!(limiter.check(ip("192.0.2.2"), "/api/a").is_ok());
+    assert!(limiter.check(client, "/blog").is_ok());
+
+    for bucket in limiter.buckets.lock().unwrap().values_mut() {
+      bucket.updated -= Duration::from_secs(1);
+    }
+    assert!(limiter.check(client, "/api/a").is_ok());
+    assert!(limiter.check(client, "/api/a").is_ok());
+    assert!(limiter.check(client, "/api/a").is_err());
+  }
+
+  #[test]
+  fn retry_after_rounds_up_to_whole_seconds() {
+    assert_eq!(ServerLimiter::retry_after(Duration::from_millis(1)), 1);
+    assert_eq!(ServerLimiter::retry_after(Duration::from_millis(1000)), 1);
+    assert_eq!(ServerLimiter::retry_after(Duration::from_millis(1500)), 2);
+  }
+
+  #[test]
+  fn buckets_are_capped_when_clients_rotate_addresses() {
+    let limiter = rate_limited(0.001, 10);
+    for n in 0..ServerLimiter::MAX_BUCKETS as u32 * 2 {
+      let client = IpAddr::from((0x2001_0db8_0000_0000_0000_0000_0000_0000u128 | u128::from(n)).to_be_bytes());
+      assert!(limiter.check(client, "/api").is_ok());
+    }
+    assert!(limiter.buckets.lock().unwrap().len() <= ServerLimiter::MAX_BUCKETS);
+  }
+
+  #[test]
+  fn forwarded_addresses_are_only_read_behind_trusted_proxies() {
+    let limiter = limiter(SiteLimitsConfig { trusted_proxies: vec![ip("10.0.0.1"), ip("10.0.0.2")], ..SiteLimitsConfig::default() });
+    let forwarded_for = |value: &str| vec![HttpHeader::new("X-Forwarded-For", value)];
+    let forwarded = |value: &str| vec![HttpHeader::new("Forwarded", value)];
+
+    assert_eq!(limiter.client(ip("192.0.2.9"), &forwarded_for("203.0.113.5")), ip("192.0.2.9"));
+    assert_eq!(limiter.client(ip("10.0.0.1"), &forwarded_for("203.0.113.5")), ip("203.0.113.5"));
+    // hops before the first untrusted one could have been made up
+    assert_eq!(limiter.client(ip("10.0.0.1"), &forwarded_for("198.51.100.1, 203.0.113.5, 10.0.0.2")), ip("203.0.113.5"));
+    assert_eq!(limiter.client(ip("10.0.0.1"), &forwarded("for=\"[2001:db8::1]:4711\";proto=https")), ip("2001:db8::1"));
+    assert_eq!(limiter.client(ip("10.0.0.1"), &forwarded("for=203.0.113.5:80, for=10.0.0.2")), ip("203.0.113.5"));
+    // Forwarded wins over X-Forwarded-For, and obfuscated hops stop the walk
+    let both = [forwarded("for=_hidden"), forwarded_for("203.0.113.5")].concat();
+    assert_eq!(limiter.client(ip("10.0.0.1"), &both), ip("10.0.0.1"));
+    assert_eq!(limiter.client(ip("10.0.0.1"), &[]), ip("10.0.0.1"));
+  }
+
+  #[test]
+  fn connections_are_capped_per_client_and_in_total() {
+    let limiter = limiter(SiteLimitsConfig {
+      max_connections: 3,
+      max_connections_per_ip: 2,
+      trusted_proxies: vec![ip("10.0.0.1")],
+      ..SiteLimitsConfig::default()
+    });
+    let first = limiter.open(ip("192.0.2.1")).expect("Under both caps");
+    let _second = limiter.open(ip("192.0.2.1")).expect("Under both caps");
+    assert!(limiter.open(ip("192.0.2.1")).is_none());
+    drop(first);
+    let _third = limiter.open(ip("192.0.2.1")).expect("A place was freed");
+
+    // a trusted proxy is only held to the global cap
+    let _proxied = limiter.open(ip("10.0.0.1")).expect("Under the global cap");
+    assert!(limiter.open(ip("10.0.0.1")).is_none());
+    assert!(limiter.open(ip("192.0.2.2")).is_none());
+  }
+}
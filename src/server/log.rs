@@ -0,0 +1,171 @@
+use std::fs;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::Local;
+
+use crate::http::header::HttpHeader;
+use crate::http::metadata::Metadata;
+use crate::http::response::Response;
+use crate::server::error::ServerError;
+use crate::site::config::{SiteLogConfig, SiteLogFormat};
+
+#[derive(Debug)]
+enum ServerLogSink {
+  Stdout,
+  File {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+  },
+}
+
+impl ServerLogSink {
+  fn open(path: &Path) -> io::Result<(fs::File, u64)> {
+    let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+  }
+
+  // access.log becomes access.log.1, access.log.1 becomes access.log.2 and so
+  // on, dropping whatever is past `keep`
+  fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    if keep == 0 {
+      return fs::remove_file(path);
+    }
+    for n in (1..keep).rev() {
+      if numbered(n).exists() {
+        fs::rename(numbered(n), numbered(n + 1))?;
+      }
+    }
+    fs::rename(path, numbered(1))
+  }
+
+  fn write_line(&mut self, line: &str) -> io::Result<()> {
+    match self {
+      ServerLogSink::Stdout => writeln!(io::stdout().lock(), "{}", line),
+      ServerLogSink::File { path, file, size, max_bytes, keep } => {
+        let length = line.len() as u64 + 1;
+        if *size > 0 && *size + length > *max_bytes {
+          Self::rotate(path, *keep)?;
+          (*file, *size) = Self::open(path)?;
+        }
+        writeln!(file, "{}", line)?;
+        *size += length;
+        Ok(())
+      }
+    }
+  }
+}
+
+// access logs go to stdout or a rotated file; errors always go to stderr so
+// they are not lost among the requests
+#[derive(Debug)]
+pub struct ServerLog {
+  format: SiteLogFormat,
+  sink: Mutex<ServerLogSink>,
+}
+
+impl ServerLog {
+  pub fn new(config: &SiteLogConfig) -> io::Result<ServerLog> {
+    let sink = match &config.path {
+      Some(path) => {
+        let (file, size) = ServerLogSink::open(path)?;
+        ServerLogSink::File { path: path.clone(), file, size, max_bytes: config.max_bytes, keep: config.keep }
+      }
+      None => ServerLogSink::Stdout,
+    };
+    Ok(ServerLog { format: config.format, sink: Mutex::new(sink) })
+  }
+
+  // quoted fields in the text formats escape quotes and backslashes like
+  // Apache does, and a missing value is written as "-"
+  fn quoted(value: Option<&str>) -> String {
+    match value {
+      Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+      None => String::from("\"-\""),
+    }
+  }
+
+  pub fn access(&self, client: IpAddr, request: &Metadata, response: &Response, started: Instant) {
+    let bytes = response.body.as_ref().map(|body| body.len()).unwrap_or_default();
+    let referer = HttpHeader::get_header(&request.headers, "Referer");
+    let user_agent = HttpHeader::get_header(&request.headers, "User-Agent");
+    let duration = started.elapsed();
+    let now = Local::now();
+
+    // a request that timed out before its request line arrived has no path
+    let has_request_line = !request.path.is_empty();
+    let request_line = has_request_line.then(|| format!("{} {} {}", request.method, request.path, request.protocol));
+    let common = format!(
+      "{} - - [{}] {} {} {}",
+      client,
+      now.format("%d/%b/%Y:%H:%M:%S %z"),
+      Self::quoted(request_line.as_deref()),
+      response.status.code(),
+      if bytes == 0 { String::from("-") } else { bytes.to_string() },
+    );
+    let line = match self.format {
+      SiteLogFormat::Common => common,
+      // the duration in microseconds goes last, like Apache's %D
+      SiteLogFormat::Combined => format!(
+        "{} {} {} {}",
+        common,
+        Self::quoted(referer.as_deref()),
+        Self::quoted(user_agent.as_deref()),
+        duration.as_micros(),
+      ),
+      SiteLogFormat::Json => serde_json::json!({
+        "time": now.to_rfc3339(),
+        "client": client.to_string(),
+        "method": has_request_line.then(|| request.method.to_string()),
+        "target": has_request_line.then_some(&request.path),
+        "protocol": has_request_line.then(|| request.protocol.to_string()),
+        "status": response.status.code(),
+        "bytes": bytes,
+        "referer": referer,
+        "user_agent": user_agent,
+        "duration_ms": duration.as_secs_f64() * 1000.0,
+      }).to_string(),
+    };
+    self.write(&line);
+  }
+
+  pub fn error(&self, client: IpAddr, error: &ServerError) {
+    let now = Local::now();
+    let line = match self.format {
+      SiteLogFormat::Json => serde_json::json!({
+        "time": now.to_rfc3339(),
+        "client": client.to_string(),
+        "error": format!("{:?}", error),
+      }).to_string(),
+      _ => format!("[{}] [error] [client {}] {:?}", now.format("%d/%b/%Y:%H:%M:%S %z"), client, error),
+    };
+    eprintln!("{}", line);
+  }
+
+  // called before exiting, so nothing written is left in a buffer
+  pub fn flush(&self) {
+    let mut sink = self.sink.lock().expect("Log sink is poisoned");
+    let flushed = match &mut *sink {
+      ServerLogSink::Stdout => io::stdout().flush(),
+      ServerLogSink::File { file, .. } => file.flush().and_then(|_| file.sync_data()),
+    };
+    if let Err(error) = flushed {
+      eprintln!("Could not flush the access log: {}", error);
+    }
+  }
+
+  fn write(&self, line: &str) {
+    let mut sink = self.sink.lock().expect("Log sink is poisoned");
+    if let Err(error) = sink.write_line(line) {
+      eprintln!("Could not write to the access log: {}", error);
+    }
+  }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::http::metadata::Metadata;
+use crate::http::response::Response;
+
+#[derive(Debug, Default)]
+struct ServerHistogram {
+  buckets: Vec<u64>,
+  sum: f64,
+  count: u64,
+}
+
+#[derive(Debug, Default)]
+struct ServerRequestMetrics {
+  requests: BTreeMap<(String, u16), u64>,
+  durations: BTreeMap<String, ServerHistogram>,
+}
+
+// decrements the open connection gauge when the connection is done with
+pub struct ServerConnectionGuard<'a>(&'a AtomicI64);
+
+impl Drop for ServerConnectionGuard<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+// counters kept in memory and rendered in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+  requests: Mutex<ServerRequestMetrics>,
+  bytes_sent: AtomicU64,
+  open_connections: AtomicI64,
+  cache_hits: AtomicU64,
+  cache_misses: AtomicU64,
+}
+
+impl ServerMetrics {
+  // upper bounds in seconds
+  const BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+  // every path that did not resolve to a route shares this label, so
+  // scanners probing for random files cannot add new series
+  const UNMATCHED_ROUTE: &str = "unmatched";
+
+  fn route(request: &Metadata, matched: bool) -> String {
+    if matched {
+      request.path.split('?').next().unwrap_or_default().to_string()
+    } else {
+      String::from(Self::UNMATCHED_ROUTE)
+    }
+  }
+
+  // `matched` is whether the router found a route for the request, whatever
+  // the response was
+  pub fn observe(&self, request: &Metadata, matched: bool, response: &Response, duration: Duration) {
+    let route = Self::route(request, matched);
+    let seconds = duration.as_secs_f64();
+    let bytes = response.body.as_ref().map(|body| body.len()).unwrap_or_default();
+    self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+
+    let mut metrics = self.requests.lock().expect("Metrics are poisoned");
+    *metrics.requests.entry((route.clone(), response.status.code())).or_default() += 1;
+    let histogram = metrics.durations.entry(route).or_default();
+    histogram.buckets.resize(Self::BUCKETS.len(), 0);
+    for (count, bound) in histogram.buckets.iter_mut().zip(Self::BUCKETS) {
+      if seconds <= *bound {
+        *count += 1;
+      }
+    }
+    histogram.sum += seconds;
+    histogram.count += 1;
+  }
+
+  pub fn open_connection(&self) -> ServerConnectionGuard<'_> {
+    self.open_connections.fetch_add(1, Ordering::Relaxed);
+    ServerConnectionGuard(&self.open_connections)
+  }
+
+  pub fn cache_lookup(&self, hit: bool) {
+    let counter = if hit { &self.cache_hits } else { &self.cache_misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+  }
+
+  fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+  }
+
+  pub fn render(&self) -> String {
+    let mut out = String::new();
+    let metrics = self.requests.lock().expect("Metrics are poisoned");
+
+    Self::header(&mut out, "site_http_requests_total", "counter", "Requests served, by route and status.");
+    for ((route, status), count) in &metrics.requests {
+      let _ = writeln!(out, "site_http_requests_total{{route=\"{}\",status=\"{}\"}} {}", Self::label(route), status, count);
+    }
+
+    Self::header(&mut out, "site_http_request_duration_seconds", "histogram", "Time from reading a request to sending the response, by route.");
+    for (route, histogram) in &metrics.durations {
+      let route = Self::label(route);
+      for (count, bound) in histogram.buckets.iter().zip(Self::BUCKETS) {
+        let _ = writeln!(out, "site_http_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}", route, bound, count);
+      }
+      let _ = writeln!(out, "site_http_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}", route, histogram.count);
+      let _ = writeln!(out, "site_http_request_duration_seconds_sum{{route=\"{}\"}} {}", route, histogram.sum);
+      let _ = writeln!(out, "site_http_request_duration_seconds_count{{route=\"{}\"}} {}", route, histogram.count);
+    }
+    drop(metrics);
+
+    Self::header(&mut out, "site_http_response_bytes_total", "counter", "Response body bytes sent.");
+    let _ = writeln!(out, "site_http_response_bytes_total {}", self.bytes_sent.load(Ordering::Relaxed));
+
+    Self::header(&mut out, "site_http_open_connections", "gauge", "Connections currently open.");
+    let _ = writeln!(out, "site_http_open_connections {}", self.open_connections.load(Ordering::Relaxed));
+
+    let (hits, misses) = (self.cache_hits.load(Ordering::Relaxed), self.cache_misses.load(Ordering::Relaxed));
+    Self::header(&mut out, "site_file_cache_lookups_total", "counter", "File cache lookups, by result.");
+    let _ = writeln!(out, "site_file_cache_lookups_total{{result=\"hit\"}} {}", hits);
+    let _ = writeln!(out, "site_file_cache_lookups_total{{result=\"miss\"}} {}", misses);
+    Self::header(&mut out, "site_file_cache_hit_ratio", "gauge", "Share of file cache lookups that were hits.");
+    let ratio = if hits + misses == 0 { 0.0 } else { hits as f64 / (hits + misses) as f64 };
+    let _ = writeln!(out, "site_file_cache_hit_ratio {}", ratio);
+
+    Self::header(&mut out, "site_build_info", "gauge", "Version of the running server.");
+    let _ = writeln!(out, "site_build_info{{version=\"{}\"}} 1", env!("CARGO_PKG_VERSION"));
+
+    out
+  }
+}
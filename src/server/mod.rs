@@ -1,3 +1,12 @@
+pub mod cache;
 pub mod error;
+pub mod h2;
 pub mod io;
+pub mod limit;
+pub mod log;
+pub mod metrics;
+pub mod reload;
 pub mod router;
+pub mod state;
+pub mod timeout;
+pub mod tls;
@@ -0,0 +1,135 @@
+use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+
+use tokio::sync::watch;
+
+use crate::site::xml::SiteXml;
+
+#[derive(Debug, Default)]
+struct LiveReloadState {
+  generation: u64,
+  error: Option<String>,
+  closed: bool,
+}
+
+// shared between the watcher, which bumps the generation after every rebuild,
+// and the event stream connections waiting to tell browsers about it; the
+// streams wait on a watch channel, so an open tab on http/2 does not hold a
+// thread, but one on 1.1 keeps the blocking thread its connection runs on
+#[derive(Debug)]
+pub struct LiveReload {
+  state: watch::Sender<LiveReloadState>,
+  blocking_streams: AtomicUsize,
+}
+
+pub struct LiveReloadStreamGuard<'a>(&'a AtomicUsize);
+
+impl Drop for LiveReloadStreamGuard<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+impl Default for LiveReload {
+  fn default() -> Self {
+    LiveReload { state: watch::Sender::new(LiveReloadState::default()), blocking_streams: AtomicUsize::new(0) }
+  }
+}
+
+impl LiveReload {
+  pub const EVENTS_PATH: &str = "/__livereload";
+  // comments sent while idle find out when the browser has gone away
+  const KEEPALIVE: Duration = Duration::from_secs(15);
+  // well under the runtime's 512 blocking threads, so tabs left open cannot
+  // starve the requests for files
+  const MAX_BLOCKING_STREAMS: usize = 64;
+  const CLIENT_SCRIPT: &str = "<script>new EventSource(\"/__livereload\").addEventListener(\"reload\", () => location.reload());</script>";
+
+  pub fn notify(&self, error: Option<String>) {
+    self.state.send_modify(|state| {
+      state.generation += 1;
+      state.error = error;
+    });
+  }
+
+  // ends every event stream, so they do not hold up a shutdown
+  pub fn close(&self) {
+    self.state.send_modify(|state| state.closed = true);
+  }
+
+  // counts a 1.1 event stream for as long as the guard lives, or None when
+  // there are already as many as are allowed
+  pub fn open_blocking_stream(&self) -> Option<LiveReloadStreamGuard<'_>> {
+    self.blocking_streams
+      .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| (open < Self::MAX_BLOCKING_STREAMS).then_some(open + 1))
+      .ok()
+      .map(|_| LiveReloadStreamGuard(&self.blocking_streams))
+  }
+
+  pub fn generation(&self) -> u64 {
+    self.state.borrow().generation
+  }
+
+  pub fn error(&self) -> Option<String> {
+    self.state.borrow().error.clone()
+  }
+
+  // waits for the next event for a client that has seen `seen`, returning the
+  // generation it has seen afterwards and the event to send, or None once the
+  // streams are closed
+  pub async fn next_event(&self, seen: u64) -> Option<(u64, String)> {
+    let mut changed = self.state.subscribe();
+    let _ = tokio::time::timeout(Self::KEEPALIVE, changed.wait_for(|state| state.generation != seen || state.closed)).await;
+    let state = self.state.borrow();
+    if state.closed {
+      None
+    } else if state.generation != seen {
+      Some((state.generation, format!("event: reload\ndata: {}\n\n", state.generation)))
+    } else {
+      Some((seen, String::from(": keepalive\n\n")))
+    }
+  }
+
+  // styled from a style element rather than attributes, since the policy can
+  // allow an inline style element by its hash but not a style attribute
+  fn overlay(error: &str) -> String {
+    format!(
+      "<style>#build-error{{position:fixed;inset:0;z-index:2147483647;overflow:auto;padding:2rem;background:rgba(24,24,24,.96);color:#ff7b72;font:14px/1.5 monospace}}#build-error p{{color:#fff;font-weight:bold}}#build-error pre{{white-space:pre-wrap}}</style><div id=\"build-error\"><p>Build failed</p><pre>{}</pre></div>",
+      SiteXml::escape(error),
+    )
+  }
+
+  // adds the reload client, and the last build error if there is one, to a
+  // page before it is sent to the browser
+  pub fn inject(&self, html: &str) -> String {
+    let snippet = match self.error() {
+      Some(error) => format!("{}{}", Self::overlay(&error), Self::CLIENT_SCRIPT),
+      None => String::from(Self::CLIENT_SCRIPT),
+    };
+    match html.rfind("</body>") {
+      Some(i) => format!("{}{}{}", &html[..i], snippet, &html[i..]),
+      None => format!("{}{}", html, snippet),
+    }
+  }
+
+  pub fn error_page(&self) -> String {
+    self.inject("<!DOCTYPE html><html><head><title>Build failed</title></head><body></body></html>")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn blocking_streams_are_capped_until_one_closes() {
+    let reload = LiveReload::default();
+    let mut streams: Vec<LiveReloadStreamGuard> = (0..LiveReload::MAX_BLOCKING_STREAMS).filter_map(|_| reload.open_blocking_stream()).collect();
+    assert_eq!(streams.len(), LiveReload::MAX_BLOCKING_STREAMS);
+    assert!(reload.open_blocking_stream().is_none());
+
+    streams.pop();
+    let reopened = reload.open_blocking_stream();
+    assert!(reopened.is_some());
+    assert!(reload.open_blocking_stream().is_none());
+  }
+}
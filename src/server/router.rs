@@ -1,20 +1,25 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::Arc;
 use std::fs;
 
 use crate::server::error::ServerError;
+use crate::site::fingerprint::SiteFingerprinter;
 
 #[derive(Debug)]
 pub struct Route {
   resource: String,
   file: String,
+  content_type: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Router {
   dist_path: String,
-  routes: Rc<[Route]>,
+  routes: Arc<[Route]>,
   default_file: String,
+  // resources renamed at build to include a hash of their contents
+  fingerprinted: HashSet<String>,
 }
 
 impl Router {
@@ -29,16 +34,20 @@ impl Router {
       dist_path: String::from(dist_path),
       routes: fs::read_to_string(Path::new(route_file)).map_err(|_| ServerError::RouteFileDoesNotExist)?
         .lines()
-        .filter(|l| !l.trim().starts_with("#") && l.trim().len() != 0)
+        .filter(|l| !l.trim().starts_with("#") && !l.trim().is_empty())
         .map(|route_line| {
-          let mut parts = route_line.split(" ");
+          let mut parts = route_line.trim().splitn(3, char::is_whitespace).map(str::trim);
           Route {
-            resource: String::from(parts.next().expect(&format!("No resource found for route: {}", route_line))),
-            file: String::from(parts.next().expect(&format!("No file found for route: {}", route_line))),
+            resource: String::from(parts.next().unwrap_or_else(|| panic!("No resource found for route: {}", route_line))),
+            file: String::from(parts.next().unwrap_or_else(|| panic!("No file found for route: {}", route_line))),
+            content_type: parts.next().filter(|c| !c.is_empty()).map(String::from),
           }
         })
         .collect(),
       default_file: String::from(default_file),
+      fingerprinted: fs::read_to_string(Path::new(dist_path).join(SiteFingerprinter::FILE))
+        .map(|table| table.lines().map(String::from).collect())
+        .unwrap_or_default(),
     })
   }
 
@@ -51,4 +60,15 @@ impl Router {
         None => (Path::new(&self.dist_path).join(&self.default_file), true)
       }
   }
+
+  pub fn resolve_content_type(&self, resource: &str) -> Option<String> {
+    self.routes
+      .iter()
+      .find(|route| route.resource == resource)
+      .and_then(|route| route.content_type.clone())
+  }
+
+  pub fn is_fingerprinted(&self, resource: &str) -> bool {
+    self.fingerprinted.contains(resource)
+  }
 }
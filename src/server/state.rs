@@ -0,0 +1,53 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::http::header::HttpHeader;
+use crate::http::metadata::Metadata;
+use crate::http::response::Response;
+use crate::server::cache::{ServerFileCache, ServerRouterCache};
+use crate::server::error::ServerError;
+use crate::server::limit::ServerLimiter;
+use crate::server::log::ServerLog;
+use crate::server::metrics::ServerMetrics;
+use crate::server::reload::LiveReload;
+use crate::site::config::SiteConfig;
+
+// everything a connection needs, cloned into each one
+#[derive(Debug, Clone)]
+pub struct ServerState {
+  pub site: Arc<SiteConfig>,
+  pub reload: Option<Arc<LiveReload>>,
+  pub log: Arc<ServerLog>,
+  pub metrics: Arc<ServerMetrics>,
+  pub files: Arc<ServerFileCache>,
+  pub routers: Arc<ServerRouterCache>,
+  pub limiter: Arc<ServerLimiter>,
+  // cancelled when the server starts shutting down
+  pub shutdown: CancellationToken,
+  // every open connection, so they can be drained before exiting
+  pub connections: TaskTracker,
+}
+
+impl ServerState {
+  // whether the path resolves to a route of the site the request is for
+  fn is_routed(&self, request: &Metadata) -> bool {
+    let host = HttpHeader::get_header(&request.headers, "Host");
+    self.site.site_root(host.as_deref())
+      .and_then(|(root, not_found)| self.routers.router(root, not_found).ok())
+      .is_some_and(|router| !router.resolve_resource_filepath(&request.path).1)
+  }
+
+  // called once a response has been sent
+  pub fn record(&self, client: IpAddr, request: &Metadata, response: &Response, started: Instant) {
+    self.metrics.observe(request, self.is_routed(request), response, started.elapsed());
+    self.log.access(client, request, response, started);
+  }
+
+  pub fn error(&self, client: IpAddr, error: &ServerError) {
+    self.log.error(client, error);
+  }
+}
@@ -0,0 +1,155 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use crate::site::config::SiteTimeoutsConfig;
+
+// what a 1.1 connection is waiting on, each with its own timeout
+#[derive(Debug, Clone, Copy)]
+pub enum ServerPhase {
+  Idle,
+  Head,
+  Body,
+  Write,
+}
+
+#[derive(Debug)]
+struct ServerDeadlineState {
+  phase: ServerPhase,
+  started: Instant,
+  timeout: Duration,
+  min_rate: u64,
+  bytes: u64,
+}
+
+// shared between a blocking handler, which moves it from phase to phase, and
+// the stream underneath it, which fails reads and writes past the deadline
+#[derive(Debug)]
+pub struct ServerDeadline {
+  config: SiteTimeoutsConfig,
+  state: Mutex<ServerDeadlineState>,
+}
+
+impl ServerDeadline {
+  // a new connection starts out waiting on its request head
+  pub fn new(config: &SiteTimeoutsConfig) -> ServerDeadline {
+    let deadline = ServerDeadline {
+      config: config.clone(),
+      state: Mutex::new(ServerDeadlineState { phase: ServerPhase::Head, started: Instant::now(), timeout: Duration::ZERO, min_rate: 0, bytes: 0 }),
+    };
+    deadline.start(ServerPhase::Head);
+    deadline
+  }
+
+  pub fn start(&self, phase: ServerPhase) {
+    let (timeout, min_rate) = match phase {
+      ServerPhase::Idle => (self.config.idle, 0),
+      ServerPhase::Head => (self.config.header, 0),
+      ServerPhase::Body => (self.config.body, self.config.min_rate),
+      ServerPhase::Write => (self.config.write, self.config.min_rate),
+    };
+    *self.state.lock().expect("Deadline is poisoned") = ServerDeadlineState {
+      phase,
+      started: Instant::now(),
+      timeout: Duration::from_secs(timeout),
+      min_rate,
+      bytes: 0,
+    };
+  }
+
+  // when the current phase runs out, including any allowance earned by bytes
+  // already transferred
+  pub fn deadline(&self) -> Instant {
+    let state = self.state.lock().expect("Deadline is poisoned");
+    let allowance = match state.min_rate {
+      0 => Duration::ZERO,
+      min_rate => Duration::from_secs_f64(state.bytes as f64 / min_rate as f64),
+    };
+    state.started + state.timeout + allowance
+  }
+
+  fn is_idle(&self) -> bool {
+    matches!(self.state.lock().expect("Deadline is poisoned").phase, ServerPhase::Idle)
+  }
+
+  fn transferred(&self, bytes: usize) {
+    self.state.lock().expect("Deadline is poisoned").bytes += bytes as u64;
+  }
+}
+
+// a stream that fails with TimedOut once the current phase's deadline passes
+// while it is waiting, so a client trickling bytes cannot hold a connection;
+// a connection idle between requests also fails as soon as the server starts
+// shutting down, instead of holding shutdown up until its idle timeout
+pub struct ServerTimeoutStream<T> {
+  inner: T,
+  deadline: Arc<ServerDeadline>,
+  sleep: Pin<Box<Sleep>>,
+  shutdown: CancellationToken,
+  cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl<T> ServerTimeoutStream<T> {
+  pub fn new(inner: T, deadline: Arc<ServerDeadline>, shutdown: CancellationToken) -> ServerTimeoutStream<T> {
+    let cancelled = Box::pin(shutdown.clone().cancelled_owned());
+    ServerTimeoutStream { inner, deadline, sleep: Box::pin(tokio::time::sleep(Duration::ZERO)), shutdown, cancelled }
+  }
+
+  // registers a wake up at the deadline, and on shutdown while idle, and fails
+  // once either has happened
+  fn poll_deadline<R>(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<R>> {
+    if self.deadline.is_idle() && (self.shutdown.is_cancelled() || self.cancelled.as_mut().poll(cx).is_ready()) {
+      return Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, "the server is shutting down")));
+    }
+    self.sleep.as_mut().reset(tokio::time::Instant::from_std(self.deadline.deadline()));
+    self.sleep.as_mut().poll(cx).map(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "the connection timed out")))
+  }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for ServerTimeoutStream<T> {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    let before = buf.filled().len();
+    match Pin::new(&mut self.inner).poll_read(cx, buf) {
+      Poll::Ready(Ok(())) => {
+        self.deadline.transferred(buf.filled().len() - before);
+        Poll::Ready(Ok(()))
+      }
+      Poll::Pending => self.poll_deadline(cx),
+      ready => ready,
+    }
+  }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for ServerTimeoutStream<T> {
+  fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    match Pin::new(&mut self.inner).poll_write(cx, buf) {
+      Poll::Ready(Ok(written)) => {
+        self.deadline.transferred(written);
+        Poll::Ready(Ok(written))
+      }
+      Poll::Pending => self.poll_deadline(cx),
+      ready => ready,
+    }
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    match Pin::new(&mut self.inner).poll_flush(cx) {
+      Poll::Pending => self.poll_deadline(cx),
+      ready => ready,
+    }
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    match Pin::new(&mut self.inner).poll_shutdown(cx) {
+      Poll::Pending => self.poll_deadline(cx),
+      ready => ready,
+    }
+  }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rustls::ServerConfig;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::http::header::HttpHeader;
+
+#[derive(Debug)]
+struct ServerTlsState {
+  modified: (Option<SystemTime>, Option<SystemTime>),
+  key: Arc<CertifiedKey>,
+}
+
+// hands every handshake the certificate from the PEM files, reading them again
+// whenever either one changes so a renewed certificate needs no restart
+#[derive(Debug)]
+pub struct ServerTls {
+  cert: PathBuf,
+  key: PathBuf,
+  provider: Arc<CryptoProvider>,
+  state: Mutex<ServerTlsState>,
+}
+
+impl ServerTls {
+  const ALPN_PROTOCOLS: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+  fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+  }
+
+  fn invalid(path: &Path, error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), error.to_string()))
+  }
+
+  fn load(cert: &Path, key: &Path, provider: &CryptoProvider) -> io::Result<CertifiedKey> {
+    let chain = CertificateDer::pem_file_iter(cert)
+      .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+      .map_err(|error| Self::invalid(cert, error))?;
+    if chain.is_empty() {
+      return Err(Self::invalid(cert, "no certificates found"));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key).map_err(|error| Self::invalid(key, error))?;
+    CertifiedKey::from_der(chain, private_key, provider).map_err(|error| Self::invalid(key, error))
+  }
+
+  pub fn new(cert: &Path, key: &Path) -> io::Result<ServerTls> {
+    let provider = Arc::new(ring::default_provider());
+    let certified_key = Self::load(cert, key, &provider)?;
+    Ok(ServerTls {
+      cert: cert.to_path_buf(),
+      key: key.to_path_buf(),
+      state: Mutex::new(ServerTlsState {
+        modified: (Self::modified(cert), Self::modified(key)),
+        key: Arc::new(certified_key),
+      }),
+      provider,
+    })
+  }
+
+  pub fn server_config(self) -> Arc<ServerConfig> {
+    let mut config = ServerConfig::builder_with_provider(self.provider.clone())
+      .with_safe_default_protocol_versions()
+      .expect("The ring provider supports the default protocol versions")
+      .with_no_client_auth()
+      .with_cert_resolver(Arc::new(self));
+    config.alpn_protocols = Self::ALPN_PROTOCOLS.iter().map(|protocol| protocol.to_vec()).collect();
+    Arc::new(config)
+  }
+
+  // where a plain request for `path` should go, or None without a Host header
+  pub fn redirect_location(host: Option<&str>, path: &str, port: u16) -> Option<String> {
+    let name = HttpHeader::host_name(host?);
+    if name.is_empty() {
+      return None;
+    }
+    Some(match port {
+      443 => format!("https://{}{}", name, path),
+      port => format!("https://{}:{}{}", name, port, path),
+    })
+  }
+}
+
+impl ResolvesServerCert for ServerTls {
+  fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+    let mut state = self.state.lock().expect("TLS state is poisoned");
+    let modified = (Self::modified(&self.cert), Self::modified(&self.key));
+    if modified != state.modified {
+      // a half written pair fails to load, and the write of the other file
+      // changes the times again, so it is simply tried on the next handshake
+      match Self::load(&self.cert, &self.key, &self.provider) {
+        Ok(key) => {
+          println!("Reloaded TLS certificate from {}", self.cert.display());
+          state.key = Arc::new(key);
+        }
+        Err(error) => eprintln!("Keeping the previous TLS certificate: {}", error),
+      }
+      state.modified = modified;
+    }
+    Some(state.key.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ServerTls;
+
+  #[test]
+  fn redirects_keep_the_path_and_drop_the_default_port() {
+    assert_eq!(ServerTls::redirect_location(Some("example.com:8000"), "/blog?page=2", 443).as_deref(), Some("https://example.com/blog?page=2"));
+    assert_eq!(ServerTls::redirect_location(Some("example.com"), "/", 8443).as_deref(), Some("https://example.com:8443/"));
+  }
+
+  #[test]
+  fn redirects_keep_ipv6_hosts_bracketed() {
+    assert_eq!(ServerTls::redirect_location(Some("[::1]:8000"), "/", 8443).as_deref(), Some("https://[::1]:8443/"));
+    assert_eq!(ServerTls::redirect_location(Some("[2001:db8::1]"), "/a", 443).as_deref(), Some("https://[2001:db8::1]/a"));
+  }
+
+  #[test]
+  fn requests_without_a_host_are_not_redirected() {
+    assert_eq!(ServerTls::redirect_location(None, "/", 443), None);
+    assert_eq!(ServerTls::redirect_location(Some(":8000"), "/", 443), None);
+  }
+}
@@ -1,16 +1,23 @@
-use std::{fs, io::{self, Write}, path::Path, rc::Rc};
+use std::{cmp::Reverse, fs, io::{self, Write}, rc::Rc};
 
-use crate::site::{fs::SiteFs, header::SiteHeader, template::SiteTemplate};
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+
+use crate::site::{csp::SiteCsp, css::SiteCss, feed::SiteFeed, fingerprint::SiteFingerprinter, fs::SiteFs, graph::SiteDependencyGraph, header::SiteHeader, headings::{SiteHeading, SiteHeadings}, highlighter::SiteHighlighter, images::SiteImages, manifest::SiteManifest, minifier::SiteMinifier, options::SiteBuildOptions, parser::SiteParser, post::SitePost, robots::SiteRobots, route::SiteRoute, sitemap::{SiteSitemap, SiteSitemapEntry}, taxonomy::SiteTaxonomy, template::SiteTemplate};
 
 pub struct SiteBuilder;
 impl SiteBuilder {
+  fn reference_regex() -> Regex {
+    Regex::new(r#"\b(?:href|src)="([^"]*)""#).unwrap()
+  }
+
   pub fn build_template_body(headers: Rc<[SiteHeader]>, body: &str, templates: Rc<[SiteTemplate]>) -> String {
     headers.iter()
         .find(|h| h.name == "template")
-        .and_then(|h| {
+        .map(|h| {
             let parent_template = templates.iter()
                 .find(|t| t.name == h.value)
-                .expect(&format!("Cannot find template: {}", h.value));
+                .unwrap_or_else(|| panic!("Cannot find template: {}", h.value));
 
             let new_body = Self::build_template_body(
                 parent_template.headers.clone(), 
@@ -18,42 +25,329 @@ impl SiteBuilder {
                 templates.clone()
             ).replace("{{ body }}", body);
 
-            Some(headers.iter()
+            headers.iter()
                 .map(|h| (format!("{{{{ {} }}}}", h.name), h.value.to_string()))
-                .fold(new_body, |acc, (find, replace)| acc.replace(&find, &replace)))
+                .fold(new_body, |acc, (find, replace)| acc.replace(&find, &replace))
         })
         .unwrap_or(body.to_string())
   }
 
-  fn minify_body(original: &str) -> String {
-    original.lines()
-        .map(|line| line.trim())
+  fn read_posts(pages: &[SiteTemplate], routes: &[SiteRoute], options: &SiteBuildOptions) -> Vec<SitePost> {
+    let mut posts: Vec<SitePost> = pages
+        .iter()
+        .filter(|template| template.path.starts_with(options.site.directories.posts()))
+        .filter_map(|template| {
+            routes.iter()
+                .find(|route| route.file == template.name)
+                .and_then(|route| SiteParser::parse_post_from_template(template, &route.resource, &options.site.build.date_format))
+        })
+        .collect();
+    posts.sort_by_key(|post| Reverse(post.published));
+    posts
+  }
+
+  fn compile_feeds(posts: &[SitePost], options: &SiteBuildOptions) -> io::Result<Vec<SiteRoute>> {
+    let feed = SiteFeed {
+        title: options.site.title.clone(),
+        description: options.site.description.clone(),
+        author: options.site.author.clone(),
+        base_url: options.site.base_url.clone(),
+        include_content: true,
+    };
+
+    fs::write(options.staging().join(SiteFeed::ATOM_FILE), feed.atom(posts))?;
+    fs::write(options.staging().join(SiteFeed::RSS_FILE), feed.rss(posts))?;
+
+    Ok(vec![
+        SiteRoute::new(&format!("/{}", SiteFeed::ATOM_FILE), SiteFeed::ATOM_FILE, Some(SiteFeed::ATOM_CONTENT_TYPE)),
+        SiteRoute::new(&format!("/{}", SiteFeed::RSS_FILE), SiteFeed::RSS_FILE, Some(SiteFeed::RSS_CONTENT_TYPE)),
+    ])
+  }
+
+  fn is_noindex(template: &SiteTemplate) -> bool {
+    SiteHeader::get_header(&template.headers, "noindex").as_deref() == Some("true")
+  }
+
+  fn last_modified(template: &SiteTemplate) -> io::Result<NaiveDate> {
+    match SiteHeader::get_header(&template.headers, "lastmod")
+        .or_else(|| SiteHeader::get_header(&template.headers, "updated"))
+        .and_then(|lastmod| NaiveDate::parse_from_str(&lastmod, "%Y-%m-%d").ok()) {
+        Some(lastmod) => Ok(lastmod),
+        None => Ok(DateTime::<Utc>::from(fs::metadata(&template.path)?.modified()?).date_naive()),
+    }
+  }
+
+  fn add_robots_meta(body: &str) -> String {
+    body.replacen("</head>", "<meta name=\"robots\" content=\"noindex\">\n</head>", 1)
+  }
+
+  fn add_stylesheet_link(body: &str, href: &str) -> String {
+    body.replacen("</head>", &format!("<link rel=\"stylesheet\" href=\"{}\">\n</head>", href), 1)
+  }
+
+  fn compile_sitemap(pages: &[SiteTemplate], routes: &[SiteRoute], options: &SiteBuildOptions) -> io::Result<Vec<SiteRoute>> {
+    let mut entries: Vec<SiteSitemapEntry> = vec![];
+    for route in routes {
+        let Some(page) = pages.iter().find(|page| page.name == route.file) else {
+            continue;
+        };
+        if Self::is_noindex(page) {
+            continue;
+        }
+
+        entries.push(SiteSitemapEntry {
+            url: format!("{}{}", options.site.base_url, route.resource),
+            lastmod: Self::last_modified(page)?,
+            changefreq: SiteHeader::get_header(&page.headers, "changefreq"),
+            priority: SiteHeader::get_header(&page.headers, "priority"),
+        });
+    }
+
+    let robots_config = SiteParser::parse_headers_from_string(&fs::read_to_string(options.site.directories.robots_file())?);
+    let sitemap_url = format!("{}/{}", options.site.base_url, SiteSitemap::FILE);
+
+    fs::write(options.staging().join(SiteSitemap::FILE), SiteSitemap::generate(&entries))?;
+    fs::write(options.staging().join(SiteRobots::FILE), SiteRobots::generate(&robots_config, &sitemap_url))?;
+
+    Ok(vec![
+        SiteRoute::new(&format!("/{}", SiteSitemap::FILE), SiteSitemap::FILE, Some(SiteSitemap::CONTENT_TYPE)),
+        SiteRoute::new(&format!("/{}", SiteRobots::FILE), SiteRobots::FILE, Some(SiteRobots::CONTENT_TYPE)),
+    ])
+  }
+
+  fn is_published(template: &SiteTemplate, options: &SiteBuildOptions) -> bool {
+    if options.include_drafts {
+        return true;
+    }
+
+    let is_draft = SiteHeader::get_header(&template.headers, "draft").as_deref() == Some("true");
+    let is_scheduled = SiteHeader::get_header(&template.headers, "published")
+        .and_then(|published| SiteParser::parse_date(&published, &options.site.build.date_format))
+        .is_some_and(|published| published > Utc::now().date_naive());
+    !is_draft && !is_scheduled
+  }
+
+  fn with_derived_headers(headers: &[SiteHeader], posts: &[SitePost], headings: &[SiteHeading], options: &SiteBuildOptions) -> Rc<[SiteHeader]> {
+    let derived = vec![
+        SiteHeader {
+            name: String::from("tag_links"),
+            value: SiteTaxonomy::tag_links(&SiteTaxonomy::parse_tags(headers)),
+        },
+        SiteHeader {
+            name: String::from("post_list"),
+            value: posts.iter().map(|post| post.list_item(&options.site.build.date_format)).collect(),
+        },
+        SiteHeader {
+            name: String::from("toc"),
+            value: SiteHeadings::table_of_contents(headings),
+        },
+    ];
+
+    headers.iter()
+        .map(|h| match (h.name.as_str(), SiteParser::parse_date(&h.value, &options.site.build.date_format)) {
+            ("published", Some(published)) => SiteHeader {
+                name: h.name.clone(),
+                value: SiteParser::format_date(published, &options.site.build.date_format),
+            },
+            _ => h.clone(),
+        })
+        .chain(derived)
+        .chain(options.site.headers())
         .collect()
   }
 
-  pub fn compile_site() -> io::Result<()> {
-    SiteFs::delete_dir_if_exists(Path::new("dist/"))?;
-    SiteFs::copy_dir_all(Path::new("content/static/"), Path::new("dist/"))?;
-    let components = SiteFs::read_components("content/components");
-    SiteFs::read_templates("content/pages", components.clone())
+  fn compile_page(template: &SiteTemplate, templates: Rc<[SiteTemplate]>, posts: &[SitePost], options: &SiteBuildOptions) -> io::Result<()> {
+    let (content, headings) = SiteHeadings::assign_ids(&template.body, options.site.build.heading_anchors);
+    let headers = Self::with_derived_headers(&template.headers, posts, &headings, options);
+    let mut body = SiteBuilder::build_template_body(headers, &content, templates);
+    if Self::is_noindex(template) {
+        body = Self::add_robots_meta(&body);
+    }
+    if SiteHighlighter::has_code_blocks(&body) {
+        body = Self::add_stylesheet_link(&SiteHighlighter::highlight(&body), &format!("/{}", SiteHighlighter::STYLESHEET_FILE));
+    }
+
+    fs::File::create(options.staging().join(&template.name))?
+        .write_all(SiteMinifier::minify(&body, &options.site.build.minify).as_bytes())
+  }
+
+  fn build_dependency_graph(pages: &[SiteTemplate], posts: &[SiteTemplate], options: &SiteBuildOptions) -> io::Result<SiteDependencyGraph> {
+    let directories = &options.site.directories;
+    let mut graph = SiteDependencyGraph::default();
+    let data = if fs::exists(directories.data())? { SiteFs::read_all_files(directories.data())? } else { vec![] };
+    for dir in [directories.templates(), directories.components(), directories.pages()] {
+        for path in SiteFs::read_all_files(dir)? {
+            graph.add_source(&path, &fs::read_to_string(&path)?, &directories.templates(), &directories.components(), &data);
+        }
+    }
+
+    for page in pages {
+        let output = directories.output.join(&page.name);
+        graph.add(&output, &page.path);
+
+        // the post list and the generated tag pages change with any post
+        let is_generated = page.path.starts_with(directories.templates());
+        if is_generated || fs::read_to_string(&page.path)?.contains("post_list") {
+            for post in posts {
+                graph.add(&output, &post.path);
+            }
+        }
+    }
+    Ok(graph)
+  }
+
+  fn add_asset_dependencies(graph: &mut SiteDependencyGraph, page: &SiteTemplate, html: &str, routes: &[SiteRoute], options: &SiteBuildOptions) {
+    let base = routes.iter()
+        .find(|route| route.file == page.name)
+        .map(|route| route.resource.clone())
+        .unwrap_or_else(|| String::from("/"));
+
+    for c in Self::reference_regex().captures_iter(html) {
+        let asset = SiteRoute::resolve_reference(&base, &c[1])
+            .and_then(|resource| routes.iter().find(|route| route.resource == resource))
+            .map(|route| options.site.directories.static_files().join(&route.file))
+            .filter(|path| path.is_file());
+        if let Some(asset) = asset {
+            graph.add(&options.site.directories.output.join(&page.name), &asset);
+        }
+    }
+  }
+
+  // builds the whole site into the staging directory; unless only checking, the
+  // pages compiled are recorded for the next incremental build
+  fn build(options: &SiteBuildOptions) -> io::Result<(SiteDependencyGraph, Vec<SiteRoute>)> {
+    let directories = &options.site.directories;
+    let staging = options.staging();
+    let persist = !options.check;
+    SiteFs::delete_dir_if_exists(&staging)?;
+    SiteFs::copy_dir_all(directories.static_files(), &staging)?;
+    let components = SiteFs::read_components(directories.components());
+    let mut routes = SiteFs::read_routes(directories.routes_file())?;
+    let data = SiteFs::read_data(directories.data())?;
+    let templates = SiteFs::read_templates(directories.templates(), components.clone(), data.clone());
+    let (mut pages, unpublished): (Vec<SiteTemplate>, Vec<SiteTemplate>) = SiteFs::read_templates(directories.pages(), components.clone(), data.clone())
         .iter()
-        .map(|template| SiteTemplate {
-            name: (*template.name).to_string(),
-            headers: template.headers.clone(),
-            body: SiteBuilder::build_template_body(
-                template.headers.clone(), 
-                &template.body, 
-                SiteFs::read_templates("content/templates", components.clone())
-            ),
-        })
-        .for_each(|page| {
-            fs::File::create(Path::new("dist")
-                .join(&page.name))
-                .expect(&format!("Could not create file: {}", &page.name))
-                .write_all(Self::minify_body(&page.body).as_bytes())
-                .expect(&format!("Could not write to file: {}", &page.name));
-        });
+        .cloned()
+        .partition(|page| Self::is_published(page, options));
+    routes.retain(|route| !unpublished.iter().any(|page| page.name == route.file));
+
+    let posts = Self::read_posts(&pages, &routes, options);
+    let post_pages: Vec<SiteTemplate> = pages.iter()
+        .filter(|page| page.path.starts_with(directories.posts()))
+        .cloned()
+        .collect();
+    let (mut tag_routes, mut tag_pages) = SiteTaxonomy::generate_pages(&posts, &directories.templates(), &options.site.build.date_format);
+    routes.append(&mut tag_routes);
+    pages.append(&mut tag_pages);
+
+    // a page is only recompiled when the hash of everything it is built from
+    // differs from the one recorded in the manifest by the previous build; the
+    // stages after this run in full every time, with image variants kept in a
+    // cache of their own
+    let mut graph = Self::build_dependency_graph(&pages, &post_pages, options)?;
+    let mut manifest = if options.incremental { SiteManifest::read(directories.manifest_file()) } else { SiteManifest::default() };
+    let salt = format!("{}\n{}", options.page_salt(), post_pages.iter().map(|post| post.name.as_str()).collect::<Vec<&str>>().join(" "));
+    let mut compiled = 0;
+    if persist {
+        fs::create_dir_all(directories.page_cache())?;
+    }
+
+    for page in &pages {
+        let output = directories.output.join(&page.name);
+        let staged = staging.join(&page.name);
+        let cached = directories.page_cache().join(&page.name);
+        let hash = graph.input_hash(&output, &salt);
+
+        if !manifest.is_current(&page.name, &hash) || !cached.is_file() {
+            Self::compile_page(page, templates.clone(), &posts, options)?;
+            if persist {
+                fs::copy(&staged, &cached)?;
+                manifest.insert(&page.name, &hash);
+            }
+            compiled += 1;
+        } else {
+            fs::copy(&cached, &staged)?;
+        }
+        Self::add_asset_dependencies(&mut graph, page, &fs::read_to_string(&staged)?, &routes, options);
+    }
+    if persist {
+        manifest.write(directories.manifest_file())?;
+    }
+    println!("Compiled {} of {} pages ({} unchanged)", compiled, pages.len(), pages.len() - compiled);
+
+    if options.site.build.images.enabled {
+        SiteImages::process(&staging, &mut routes, &options.site.build.images, &directories.image_cache())?;
+    }
+
+    fs::write(staging.join(SiteHighlighter::STYLESHEET_FILE), SiteHighlighter::stylesheet())?;
+    routes.push(SiteRoute::new(&format!("/{}", SiteHighlighter::STYLESHEET_FILE), SiteHighlighter::STYLESHEET_FILE, None));
+
+    routes.append(&mut Self::compile_feeds(&posts, options)?);
+    routes.append(&mut Self::compile_sitemap(&pages, &routes, options)?);
+    SiteCss::process(&staging, &mut routes, &options.site.build.css)?;
+    if options.fingerprint_assets {
+        SiteFingerprinter::fingerprint(&staging, &mut routes)?;
+    }
+    // hashed last, since anything before may still rewrite the pages
+    let security = &options.site.server.security;
+    if security.hash_inline && !security.content_security_policy.is_empty() {
+        SiteCsp::write(&staging, &routes)?;
+    }
+    SiteFs::write_routes(staging.join("routes.txt"), &routes)?;
+
+    Ok((graph, routes))
+  }
+
+  pub fn compile_site(options: &SiteBuildOptions) -> io::Result<SiteDependencyGraph> {
+    let (graph, _) = Self::build(options)?;
+    SiteFs::sync_dir(options.staging(), &options.site.directories.output)?;
+    Ok(graph)
+  }
+
+  // builds the site in a temporary directory, without touching the output
+  // directory or the manifest, and reports routes without files, links to
+  // resources that have no route and inline content the content security
+  // policy would block
+  pub fn check_site(options: &SiteBuildOptions) -> io::Result<Vec<String>> {
+    let problems = Self::build(options).and_then(|(_, routes)| Self::find_problems(&routes, options));
+    SiteFs::delete_dir_if_exists(options.staging())?;
+    problems
+  }
+
+  fn find_problems(routes: &[SiteRoute], options: &SiteBuildOptions) -> io::Result<Vec<String>> {
+    let staging = options.staging();
+    let mut problems = vec![];
+
+    for route in routes {
+        if !staging.join(&route.file).is_file() {
+            problems.push(format!("{}: route points at missing file {}", route.resource, route.file));
+        }
+    }
+
+    let reference_regex = Self::reference_regex();
+    let security = &options.site.server.security;
+    for route in routes.iter().filter(|route| route.file.ends_with(".html")) {
+        let Ok(html) = fs::read_to_string(staging.join(&route.file)) else {
+            continue;
+        };
+        if !security.content_security_policy.is_empty() {
+            for violation in SiteCsp::violations(&html, &security.content_security_policy, security.hash_inline) {
+                problems.push(format!("{}: {}", route.resource, violation));
+            }
+        }
+        for c in reference_regex.captures_iter(&html) {
+            let Some(resource) = SiteRoute::resolve_reference(&route.resource, &c[1]) else {
+                continue;
+            };
+            let resource = resource.split(['?', '#']).next().unwrap_or_default();
+            if !routes.iter().any(|r| r.resource == resource) {
+                problems.push(format!("{}: broken link to {}", route.resource, resource));
+            }
+        }
+    }
 
-    Ok(())
+    problems.sort();
+    problems.dedup();
+    Ok(problems)
   }
 }
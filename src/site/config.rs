@@ -0,0 +1,458 @@
+use std::{collections::BTreeMap, fmt::Write, fs, io, net::IpAddr, path::{Path, PathBuf}};
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::http::header::HttpHeader;
+use crate::site::{css::SiteCssOptions, header::SiteHeader, images::SiteImageOptions, minifier::SiteMinifierOptions};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteDirectories {
+  pub content: PathBuf,
+  pub output: PathBuf,
+  pub cache: PathBuf,
+}
+
+impl Default for SiteDirectories {
+  fn default() -> Self {
+    SiteDirectories {
+      content: PathBuf::from("content"),
+      output: PathBuf::from("dist"),
+      cache: PathBuf::from(".cache"),
+    }
+  }
+}
+
+impl SiteDirectories {
+  pub fn templates(&self) -> PathBuf {
+    self.content.join("templates")
+  }
+
+  pub fn components(&self) -> PathBuf {
+    self.content.join("components")
+  }
+
+  pub fn data(&self) -> PathBuf {
+    self.content.join("data")
+  }
+
+  pub fn pages(&self) -> PathBuf {
+    self.content.join("pages")
+  }
+
+  pub fn posts(&self) -> PathBuf {
+    self.pages().join("blog")
+  }
+
+  pub fn static_files(&self) -> PathBuf {
+    self.content.join("static")
+  }
+
+  pub fn routes_file(&self) -> PathBuf {
+    self.static_files().join("routes.txt")
+  }
+
+  pub fn robots_file(&self) -> PathBuf {
+    self.content.join("robots.conf")
+  }
+
+  // everything is built here first and then synced into the output, so files
+  // that did not change keep their modification times and stale ones are removed
+  pub fn build(&self) -> PathBuf {
+    self.cache.join("build")
+  }
+
+  pub fn page_cache(&self) -> PathBuf {
+    self.cache.join("pages")
+  }
+
+  pub fn image_cache(&self) -> PathBuf {
+    self.cache.join("images")
+  }
+
+  pub fn manifest_file(&self) -> PathBuf {
+    self.cache.join("manifest.txt")
+  }
+}
+
+// how pages are rendered; `date_format` is a chrono pattern where %o is also
+// the day of the month with its ordinal suffix, e.g. "1st", and
+// `heading_anchors` adds a "#" self link to every section heading
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteBuildConfig {
+  pub date_format: String,
+  pub heading_anchors: bool,
+  pub minify: SiteMinifierOptions,
+  pub css: SiteCssOptions,
+  pub images: SiteImageOptions,
+}
+
+impl Default for SiteBuildConfig {
+  fn default() -> Self {
+    SiteBuildConfig {
+      date_format: String::from("%B %o, %Y"),
+      heading_anchors: true,
+      minify: SiteMinifierOptions::default(),
+      css: SiteCssOptions::default(),
+      images: SiteImageOptions::default(),
+    }
+  }
+}
+
+// headers added to responses for resources matching `path`, where a trailing
+// `*` matches any suffix
+#[derive(Debug, Clone, Deserialize)]
+pub struct SitePathHeaders {
+  pub path: String,
+  pub headers: BTreeMap<String, String>,
+}
+
+impl SitePathHeaders {
+  pub fn matches(&self, resource: &str) -> bool {
+    match self.path.strip_suffix('*') {
+      Some(prefix) => resource.starts_with(prefix),
+      None => resource == self.path,
+    }
+  }
+}
+
+// a separately built site served to requests for `name`, with its own route
+// table and 404 page
+#[derive(Debug, Clone, Deserialize)]
+pub struct SiteVirtualHost {
+  pub name: String,
+  pub root: PathBuf,
+  pub not_found: Option<String>,
+}
+
+// with this set the site is served over https on `port`, and the plain listener
+// only redirects there
+#[derive(Debug, Clone, Deserialize)]
+pub struct SiteTlsConfig {
+  pub cert: PathBuf,
+  pub key: PathBuf,
+  #[serde(default = "SiteTlsConfig::default_port")]
+  pub port: u16,
+  #[serde(default = "SiteTlsConfig::default_hsts_max_age")]
+  pub hsts_max_age: u64,
+  #[serde(default)]
+  pub hsts_include_subdomains: bool,
+  #[serde(default)]
+  pub hsts_preload: bool,
+}
+
+impl SiteTlsConfig {
+  fn default_port() -> u16 {
+    8443
+  }
+
+  fn default_hsts_max_age() -> u64 {
+    31536000
+  }
+
+  pub fn hsts(&self) -> String {
+    let mut value = format!("max-age={}", self.hsts_max_age);
+    if self.hsts_include_subdomains {
+      value.push_str("; includeSubDomains");
+    }
+    if self.hsts_preload {
+      value.push_str("; preload");
+    }
+    value
+  }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SiteLogFormat {
+  Common,
+  Combined,
+  Json,
+}
+
+// access logs go to stdout unless `path` is set, in which case the file is
+// rotated once it would grow past `max_bytes`, keeping `keep` old ones
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteLogConfig {
+  pub format: SiteLogFormat,
+  pub path: Option<PathBuf>,
+  pub max_bytes: u64,
+  pub keep: usize,
+}
+
+impl Default for SiteLogConfig {
+  fn default() -> Self {
+    SiteLogConfig {
+      format: SiteLogFormat::Combined,
+      path: None,
+      max_bytes: 10 * 1024 * 1024,
+      keep: 5,
+    }
+  }
+}
+
+// metrics are served at `path` on the main port, or on `port` alone when
+// that is set so they can be kept off the public listener
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteMetricsConfig {
+  pub enabled: bool,
+  pub path: String,
+  pub port: Option<u16>,
+}
+
+impl Default for SiteMetricsConfig {
+  fn default() -> Self {
+    SiteMetricsConfig {
+      enabled: false,
+      path: String::from("/metrics"),
+      port: None,
+    }
+  }
+}
+
+// requests to paths starting with `prefix` refill at `rate` per second per
+// client, up to `burst` at once
+#[derive(Debug, Clone, Deserialize)]
+pub struct SiteRateLimit {
+  pub prefix: String,
+  pub rate: f64,
+  pub burst: u32,
+}
+
+// connection caps apply to the peer address; behind one of the
+// `trusted_proxies` the client is read from X-Forwarded-For or Forwarded
+// instead, and only the global cap applies to the proxy itself
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteLimitsConfig {
+  pub max_connections: usize,
+  pub max_connections_per_ip: usize,
+  pub trusted_proxies: Vec<IpAddr>,
+  pub routes: Vec<SiteRateLimit>,
+}
+
+impl Default for SiteLimitsConfig {
+  fn default() -> Self {
+    SiteLimitsConfig {
+      max_connections: 1024,
+      max_connections_per_ip: 64,
+      trusted_proxies: vec![],
+      routes: vec![],
+    }
+  }
+}
+
+// headers sent with every response unless a path overrides them, where an
+// empty value leaves the header out; with `hash_inline` the build records a
+// hash of every inline script and style, which is added to the policy of the
+// page they are on
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteSecurityConfig {
+  pub content_security_policy: String,
+  pub hash_inline: bool,
+  pub referrer_policy: String,
+  pub permissions_policy: String,
+  pub frame_options: String,
+  pub cross_origin_opener_policy: String,
+}
+
+impl Default for SiteSecurityConfig {
+  fn default() -> Self {
+    SiteSecurityConfig {
+      content_security_policy: String::new(),
+      hash_inline: true,
+      referrer_policy: String::from("strict-origin-when-cross-origin"),
+      permissions_policy: String::from("camera=(), microphone=(), geolocation=()"),
+      frame_options: String::from("DENY"),
+      cross_origin_opener_policy: String::from("same-origin"),
+    }
+  }
+}
+
+impl SiteSecurityConfig {
+  pub fn headers(&self) -> Vec<(&'static str, &str)> {
+    [
+      ("Content-Security-Policy", &self.content_security_policy),
+      ("Referrer-Policy", &self.referrer_policy),
+      ("Permissions-Policy", &self.permissions_policy),
+      ("X-Frame-Options", &self.frame_options),
+      ("Cross-Origin-Opener-Policy", &self.cross_origin_opener_policy),
+    ]
+      .into_iter()
+      .filter(|(_, value)| !value.is_empty())
+      .map(|(name, value)| (name, value.as_str()))
+      .collect()
+  }
+}
+
+// in seconds; a request body or a response may take its timeout plus one more
+// second for every `min_rate` bytes that have moved, which 0 turns off
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteTimeoutsConfig {
+  pub header: u64,
+  pub body: u64,
+  pub idle: u64,
+  pub write: u64,
+  pub min_rate: u64,
+}
+
+impl Default for SiteTimeoutsConfig {
+  fn default() -> Self {
+    SiteTimeoutsConfig {
+      header: 10,
+      body: 30,
+      idle: 5,
+      write: 30,
+      min_rate: 1024,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteServerConfig {
+  pub addr: IpAddr,
+  pub port: u16,
+  pub name: String,
+  pub not_found: String,
+  pub headers: BTreeMap<String, String>,
+  pub paths: Vec<SitePathHeaders>,
+  // extension to content type, replacing the built in table
+  pub mime_types: BTreeMap<String, String>,
+  pub tls: Option<SiteTlsConfig>,
+  pub hosts: Vec<SiteVirtualHost>,
+  // "main" serves the main site to hosts not listed in `hosts`, "reject"
+  // answers them with 421, and a listed name serves that host's site
+  pub unknown_hosts: String,
+  // seconds open connections get to finish after a shutdown signal
+  pub drain_timeout: u64,
+  pub log: SiteLogConfig,
+  pub metrics: SiteMetricsConfig,
+  pub limits: SiteLimitsConfig,
+  pub timeouts: SiteTimeoutsConfig,
+  pub security: SiteSecurityConfig,
+}
+
+impl Default for SiteServerConfig {
+  fn default() -> Self {
+    SiteServerConfig {
+      addr: IpAddr::from([0, 0, 0, 0]),
+      port: 8000,
+      name: String::from("Christian's Content Server v0.1"),
+      not_found: String::from("404.html"),
+      headers: BTreeMap::new(),
+      paths: vec![],
+      mime_types: BTreeMap::new(),
+      tls: None,
+      hosts: vec![],
+      unknown_hosts: String::from("main"),
+      drain_timeout: 10,
+      log: SiteLogConfig::default(),
+      metrics: SiteMetricsConfig::default(),
+      limits: SiteLimitsConfig::default(),
+      timeouts: SiteTimeoutsConfig::default(),
+      security: SiteSecurityConfig::default(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteConfig {
+  pub base_url: String,
+  pub title: String,
+  pub author: String,
+  pub description: String,
+  pub language: String,
+  pub directories: SiteDirectories,
+  pub build: SiteBuildConfig,
+  pub server: SiteServerConfig,
+}
+
+impl Default for SiteConfig {
+  fn default() -> Self {
+    SiteConfig {
+      base_url: String::from("https://cbernier.com"),
+      title: String::from("Christian Bernier"),
+      author: String::from("Christian Bernier"),
+      description: String::from("Thoughts on side projects and ideas from Christian Bernier"),
+      language: String::from("en-US"),
+      directories: SiteDirectories::default(),
+      build: SiteBuildConfig::default(),
+      server: SiteServerConfig::default(),
+    }
+  }
+}
+
+impl SiteConfig {
+  pub const FILE: &str = "site.toml";
+
+  // a missing file means the defaults, but one that does not parse is an error
+  pub fn read(path: impl AsRef<Path>) -> io::Result<SiteConfig> {
+    if !fs::exists(&path)? {
+      return Ok(SiteConfig::default());
+    }
+    toml::from_str::<SiteConfig>(&fs::read_to_string(&path)?)
+      .map_err(|error| error.to_string())
+      .and_then(SiteConfig::validate)
+      .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.as_ref().display(), error)))
+  }
+
+  fn validate(self) -> Result<SiteConfig, String> {
+    let unknown_hosts = &self.server.unknown_hosts;
+    if unknown_hosts != "main" && unknown_hosts != "reject" && !self.server.hosts.iter().any(|host| &host.name == unknown_hosts) {
+      return Err(format!("unknown_hosts must be \"main\", \"reject\" or one of the names in [[server.hosts]], not \"{}\"", unknown_hosts));
+    }
+    if let Some(limit) = self.server.limits.routes.iter().find(|limit| !limit.rate.is_finite() || limit.rate <= 0.0 || limit.burst == 0) {
+      return Err(format!("the rate limit for \"{}\" needs a positive rate and burst", limit.prefix));
+    }
+    if self.build.images.widths.contains(&0) {
+      return Err(String::from("image widths must be positive"));
+    }
+    // chrono only fails once the date is written, so the pattern is tried
+    // here rather than panicking halfway through a build
+    let mut formatted = String::new();
+    if write!(formatted, "{}", NaiveDate::default().format(&self.build.date_format.replace("%o", "1st"))).is_err() {
+      return Err(format!("date_format \"{}\" is not a valid date pattern", self.build.date_format));
+    }
+    Ok(self)
+  }
+
+  // the directory and 404 page to serve a request for `host` from, or None
+  // when it should be turned away
+  pub fn site_root(&self, host: Option<&str>) -> Option<(&Path, &str)> {
+    let name = host.map(HttpHeader::host_name).unwrap_or_default();
+    let virtual_host = |name: &str| self.server.hosts.iter().find(|host| host.name.eq_ignore_ascii_case(name));
+    let main = (self.directories.output.as_path(), self.server.not_found.as_str());
+
+    let host = match virtual_host(name) {
+      Some(host) => host,
+      None => match self.server.unknown_hosts.as_str() {
+        "main" => return Some(main),
+        "reject" => return None,
+        fallback => virtual_host(fallback)?,
+      },
+    };
+    Some((host.root.as_path(), host.not_found.as_deref().unwrap_or(main.1)))
+  }
+
+  // exposed to templates as {{ site.title }} and so on
+  pub fn headers(&self) -> Vec<SiteHeader> {
+    [
+      ("site.base_url", &self.base_url),
+      ("site.title", &self.title),
+      ("site.author", &self.author),
+      ("site.description", &self.description),
+      ("site.language", &self.language),
+    ]
+      .into_iter()
+      .map(|(name, value)| SiteHeader { name: String::from(name), value: value.clone() })
+      .collect()
+  }
+}
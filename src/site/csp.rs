@@ -0,0 +1,238 @@
+use std::{fs, io, path::Path};
+
+use regex::Regex;
+
+use crate::site::{hash::SiteHash, route::SiteRoute};
+
+// hash sources for the inline scripts and styles of every page, recorded at
+// build time so a policy without 'unsafe-inline' still lets them run; the
+// table is stored one "file directive source" triple per line
+pub struct SiteCsp;
+impl SiteCsp {
+  pub const FILE: &str = "csp.txt";
+
+  fn script_regex() -> Regex {
+    Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script>").unwrap()
+  }
+
+  fn style_regex() -> Regex {
+    Regex::new(r"(?is)<style\b[^>]*>(.*?)</style>").unwrap()
+  }
+
+  fn src_regex() -> Regex {
+    Regex::new(r"(?i)\bsrc\s*=").unwrap()
+  }
+
+  fn handler_regex() -> Regex {
+    Regex::new(r"(?i)<[a-z][^>]*\s(on[a-z]+)\s*=").unwrap()
+  }
+
+  fn javascript_url_regex() -> Regex {
+    Regex::new(r#"(?i)\b(?:href|src|action)\s*=\s*["']?\s*javascript:"#).unwrap()
+  }
+
+  fn style_attribute_regex() -> Regex {
+    Regex::new(r"(?i)<[a-z][^>]*\sstyle\s*=").unwrap()
+  }
+
+  fn hash_source(content: &str) -> String {
+    format!("'sha256-{}'", SiteHash::sha256_base64(content.as_bytes()))
+  }
+
+  // the directive and hash source for each inline script and style in a page
+  pub fn sources(html: &str) -> Vec<(String, String)> {
+    let (src_regex, script_regex, style_regex) = (Self::src_regex(), Self::script_regex(), Self::style_regex());
+    let scripts = script_regex
+      .captures_iter(html)
+      .filter(|c| !src_regex.is_match(&c[1]))
+      .map(|c| (String::from("script-src"), Self::hash_source(&c[2])));
+    let styles = style_regex
+      .captures_iter(html)
+      .map(|c| (String::from("style-src"), Self::hash_source(&c[1])));
+
+    let mut sources: Vec<(String, String)> = scripts.chain(styles).collect();
+    sources.sort();
+    sources.dedup();
+    sources
+  }
+
+  pub fn write(dist: &Path, routes: &[SiteRoute]) -> io::Result<()> {
+    let mut lines = String::new();
+    for route in routes.iter().filter(|route| route.file.ends_with(".html")) {
+      for (directive, source) in Self::sources(&fs::read_to_string(dist.join(&route.file))?) {
+        lines.push_str(&format!("{} {} {}\n", route.file, directive, source));
+      }
+    }
+    fs::write(dist.join(Self::FILE), lines)
+  }
+
+  // the sources recorded for `file` in a table written by `write`
+  pub fn read(table: &str, file: &str) -> Vec<(String, String)> {
+    table.lines()
+      .filter_map(|line| {
+        let mut parts = line.split(' ');
+        (parts.next()? == file).then_some((String::from(parts.next()?), String::from(parts.next()?)))
+      })
+      .collect()
+  }
+
+  fn parse(policy: &str) -> Vec<(String, Vec<String>)> {
+    policy.split(';')
+      .filter_map(|directive| {
+        let mut parts = directive.split_whitespace();
+        Some((parts.next()?.to_lowercase(), parts.map(String::from).collect()))
+      })
+      .collect()
+  }
+
+  // the sources that apply to `name`, falling back to default-src, or None
+  // when the policy does not restrict it at all
+  fn effective<'a>(directives: &'a [(String, Vec<String>)], name: &str) -> Option<&'a [String]> {
+    directives.iter()
+      .find(|(directive, _)| directive == name)
+      .or_else(|| directives.iter().find(|(directive, _)| directive == "default-src"))
+      .map(|(_, values)| values.as_slice())
+  }
+
+  fn allows_inline(values: &[String]) -> bool {
+    values.iter().any(|value| value.eq_ignore_ascii_case("'unsafe-inline'"))
+  }
+
+  // adds a page's hash sources to the configured policy; a directive that is
+  // missing starts out as a copy of default-src, and one that already allows
+  // 'unsafe-inline' is left alone, since browsers ignore that next to a hash
+  pub fn policy(configured: &str, sources: &[(String, String)]) -> String {
+    let mut directives = Self::parse(configured);
+    for name in ["script-src", "style-src"] {
+      let hashes: Vec<&String> = sources.iter().filter(|(directive, _)| directive == name).map(|(_, source)| source).collect();
+      let Some(values) = Self::effective(&directives, name).filter(|_| !hashes.is_empty()).map(<[String]>::to_vec) else {
+        continue;
+      };
+      if Self::allows_inline(&values) {
+        continue;
+      }
+      let index = match directives.iter().position(|(directive, _)| directive == name) {
+        Some(index) => index,
+        None => {
+          directives.push((String::from(name), values));
+          directives.len() - 1
+        }
+      };
+      let values = &mut directives[index].1;
+      values.retain(|value| !value.eq_ignore_ascii_case("'none'"));
+      for hash in hashes {
+        if !values.contains(hash) {
+          values.push(hash.clone());
+        }
+      }
+    }
+
+    directives.iter()
+      .map(|(name, values)| [name.clone()].into_iter().chain(values.iter().cloned()).collect::<Vec<String>>().join(" "))
+      .collect::<Vec<String>>()
+      .join("; ")
+  }
+
+  // inline content in a page that the configured policy would block, even
+  // with the build time hashes when `hash_inline` is on
+  pub fn violations(html: &str, configured: &str, hash_inline: bool) -> Vec<String> {
+    let directives = Self::parse(configured);
+    let mut violations = vec![];
+
+    if let Some(scripts) = Self::effective(&directives, "script-src").filter(|values| !Self::allows_inline(values)) {
+      for c in Self::handler_regex().captures_iter(html) {
+        violations.push(format!("inline event handler {} is blocked by script-src", c[1].to_lowercase()));
+      }
+      if Self::javascript_url_regex().is_match(html) {
+        violations.push(String::from("javascript: URL is blocked by script-src"));
+      }
+      if !hash_inline && Self::sources(html).iter().any(|(directive, source)| directive == "script-src" && !scripts.contains(source)) {
+        violations.push(String::from("inline script is blocked by script-src"));
+      }
+    }
+
+    if let Some(styles) = Self::effective(&directives, "style-src").filter(|values| !Self::allows_inline(values)) {
+      if Self::style_attribute_regex().is_match(html) {
+        violations.push(String::from("style attribute is blocked by style-src"));
+      }
+      if !hash_inline && Self::sources(html).iter().any(|(directive, source)| directive == "style-src" && !styles.contains(source)) {
+        violations.push(String::from("inline style is blocked by style-src"));
+      }
+    }
+
+    violations.sort();
+    violations.dedup();
+    violations
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn hash(content: &str) -> String {
+    SiteCsp::hash_source(content)
+  }
+
+  #[test]
+  fn sources_hash_inline_scripts_and_styles_but_skip_external_scripts() {
+    let html = "<script>a()</script><script src=\"/app.js\"></script><SCRIPT type=module>a()</SCRIPT><style>p{}</style>";
+    assert_eq!(SiteCsp::sources(html), vec![
+      (String::from("script-src"), hash("a()")),
+      (String::from("style-src"), hash("p{}")),
+    ]);
+  }
+
+  #[test]
+  fn read_returns_only_the_sources_for_a_file() {
+    let table = "index.html script-src 'sha256-a'\nabout.html style-src 'sha256-b'\nindex.html style-src 'sha256-c'\n";
+    assert_eq!(SiteCsp::read(table, "index.html"), vec![
+      (String::from("script-src"), String::from("'sha256-a'")),
+      (String::from("style-src"), String::from("'sha256-c'")),
+    ]);
+    assert_eq!(SiteCsp::read(table, "missing.html"), vec![]);
+  }
+
+  #[test]
+  fn policy_copies_default_src_into_a_missing_directive() {
+    let sources = [(String::from("script-src"), hash("a()"))];
+    assert_eq!(
+      SiteCsp::policy("default-src 'self'; img-src *", &sources),
+      format!("default-src 'self'; img-src *; script-src 'self' {}", hash("a()")),
+    );
+    // without default-src the page is unrestricted, so nothing is added
+    assert_eq!(SiteCsp::policy("img-src *", &sources), "img-src *");
+  }
+
+  #[test]
+  fn policy_drops_none_when_it_adds_hashes() {
+    let sources = [(String::from("style-src"), hash("p{}"))];
+    assert_eq!(
+      SiteCsp::policy("default-src 'none'; style-src 'none'", &sources),
+      format!("default-src 'none'; style-src {}", hash("p{}")),
+    );
+    assert_eq!(SiteCsp::policy("default-src 'none'; style-src 'none'", &[]), "default-src 'none'; style-src 'none'");
+  }
+
+  #[test]
+  fn policy_leaves_unsafe_inline_alone() {
+    let sources = [(String::from("script-src"), hash("a()")), (String::from("style-src"), hash("p{}"))];
+    assert_eq!(
+      SiteCsp::policy("script-src 'self' 'UNSAFE-INLINE'; style-src 'self'", &sources),
+      format!("script-src 'self' 'UNSAFE-INLINE'; style-src 'self' {}", hash("p{}")),
+    );
+  }
+
+  #[test]
+  fn violations_report_what_hashes_cannot_allow() {
+    let html = "<button onClick=\"go()\" style=\"color:red\">go</button><a href=\"javascript:go()\">go</a><script>a()</script>";
+    assert_eq!(SiteCsp::violations(html, "default-src 'self'", true), vec![
+      String::from("inline event handler onclick is blocked by script-src"),
+      String::from("javascript: URL is blocked by script-src"),
+      String::from("style attribute is blocked by style-src"),
+    ]);
+    assert!(SiteCsp::violations(html, "default-src 'self'", false).contains(&String::from("inline script is blocked by script-src")));
+    assert_eq!(SiteCsp::violations(html, "default-src 'self' 'unsafe-inline'", false), Vec::<String>::new());
+    assert_eq!(SiteCsp::violations(html, "img-src *", false), Vec::<String>::new());
+  }
+}
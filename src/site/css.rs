@@ -0,0 +1,469 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs, io, path::Path};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::site::{hash::SiteHash, route::SiteRoute, xml::SiteXml};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteCssOptions {
+  pub minify: bool,
+  pub bundle: bool,
+  pub inline_critical: bool,
+}
+
+impl Default for SiteCssOptions {
+  fn default() -> Self {
+    SiteCssOptions {
+      minify: true,
+      bundle: true,
+      inline_critical: false,
+    }
+  }
+}
+
+#[derive(Debug)]
+enum CssNode {
+  Statement(String),
+  Rule { prelude: String, block: String },
+}
+
+pub struct SiteCss;
+impl SiteCss {
+  const NESTED_RULE_AT_RULES: [&str; 4] = ["@media", "@supports", "@layer", "@container"];
+  // deferred stylesheets are linked with media="print", which browsers fetch
+  // without blocking the first render, and switched to all once loaded
+  const DEFERRED_LOADER: &str = "<script>document.querySelectorAll('link[data-deferred]').forEach(function (link) { if (link.sheet) { link.media = 'all'; } else { link.addEventListener('load', function () { link.media = 'all'; }); } });</script>";
+
+  fn import_regex() -> Regex {
+    Regex::new(r#"^@import\s+(?:url\(\s*['"]?([^'")]+)['"]?\s*\)|['"]([^'"]+)['"])\s*([^;]*);?$"#).unwrap()
+  }
+
+  fn stylesheet_link_regex() -> Regex {
+    Regex::new(r"<link\s[^>]*>").unwrap()
+  }
+
+  fn element_regex() -> Regex {
+    Regex::new(r"<([a-zA-Z][a-zA-Z0-9-]*)").unwrap()
+  }
+
+  fn class_regex() -> Regex {
+    Regex::new(r#"\bclass="([^"]*)""#).unwrap()
+  }
+
+  fn id_regex() -> Regex {
+    Regex::new(r#"\bid="([^"]*)""#).unwrap()
+  }
+
+  fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+      match (quote, c) {
+        (Some(q), c) => {
+          stripped.push(c);
+          if c == '\\' {
+            stripped.extend(chars.next());
+          } else if c == q {
+            quote = None;
+          }
+        }
+        (None, '"' | '\'') => {
+          quote = Some(c);
+          stripped.push(c);
+        }
+        (None, '/') if chars.peek() == Some(&'*') => {
+          chars.next();
+          let mut previous = ' ';
+          for c in chars.by_ref() {
+            if previous == '*' && c == '/' {
+              break;
+            }
+            previous = c;
+          }
+        }
+        (None, c) => stripped.push(c),
+      }
+    }
+    stripped
+  }
+
+  // splits a stylesheet into its top-level statements and rules, keeping the
+  // contents of each block raw so nested at-rules can be parsed on demand
+  fn parse(css: &str) -> Vec<CssNode> {
+    let css = Self::strip_comments(css);
+    let mut nodes = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut block_start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in css.char_indices() {
+      if let Some(q) = quote {
+        if escaped {
+          escaped = false;
+        } else if c == '\\' {
+          escaped = true;
+        } else if c == q {
+          quote = None;
+        }
+        continue;
+      }
+
+      match c {
+        '"' | '\'' => quote = Some(c),
+        '{' => {
+          if depth == 0 {
+            block_start = i;
+          }
+          depth += 1;
+        }
+        '}' if depth > 0 => {
+          depth -= 1;
+          if depth == 0 {
+            nodes.push(CssNode::Rule {
+              prelude: String::from(css[start..block_start].trim()),
+              block: String::from(&css[block_start + 1..i]),
+            });
+            start = i + 1;
+          }
+        }
+        ';' if depth == 0 => {
+          let statement = css[start..=i].trim();
+          if statement != ";" {
+            nodes.push(CssNode::Statement(String::from(statement)));
+          }
+          start = i + 1;
+        }
+        _ => (),
+      }
+    }
+
+    let rest = css[start..].trim();
+    if !rest.is_empty() {
+      nodes.push(CssNode::Statement(String::from(rest)));
+    }
+    nodes
+  }
+
+  fn has_nested_rules(prelude: &str) -> bool {
+    Self::NESTED_RULE_AT_RULES.iter().any(|at_rule| prelude.starts_with(at_rule))
+  }
+
+  fn format(nodes: &[CssNode]) -> String {
+    nodes.iter()
+      .map(|node| match node {
+        CssNode::Statement(statement) => format!("{}\n", statement),
+        CssNode::Rule { prelude, block } => format!("{} {{{}}}\n", prelude, block),
+      })
+      .collect()
+  }
+
+  // inlines @import statements, resolving each relative to the importing
+  // stylesheet; imports that cannot be loaded are left for the browser
+  pub fn resolve_imports(css: &str, resource: &str, load: &dyn Fn(&str) -> Option<String>) -> String {
+    Self::resolve_imports_from(css, resource, load, &mut HashSet::from([String::from(resource)]))
+  }
+
+  fn resolve_imports_from(css: &str, resource: &str, load: &dyn Fn(&str) -> Option<String>, seen: &mut HashSet<String>) -> String {
+    Self::parse(css)
+      .into_iter()
+      .map(|node| {
+        let CssNode::Statement(statement) = &node else {
+          return Self::format(&[node]);
+        };
+        let Some(captures) = Self::import_regex().captures(statement) else {
+          return Self::format(&[node]);
+        };
+
+        let reference = captures.get(1).or(captures.get(2)).map(|m| m.as_str()).unwrap_or_default();
+        let imported = SiteRoute::resolve_reference(resource, reference)
+          .filter(|imported| seen.insert(imported.clone()))
+          .and_then(|imported| load(&imported).map(|css| (imported, css)));
+
+        match imported {
+          Some((imported, css)) => {
+            let css = Self::resolve_imports_from(&css, &imported, load, seen);
+            match captures[3].trim() {
+              "" => css,
+              media => format!("@media {} {{\n{}}}\n", media, css),
+            }
+          }
+          None => Self::format(&[node]),
+        }
+      })
+      .collect()
+  }
+
+  pub fn minify(css: &str) -> String {
+    let css = Self::strip_comments(css);
+    let mut minified = String::with_capacity(css.len());
+    // whether each open block holds declarations (rather than nested rules)
+    let mut blocks: Vec<bool> = vec![];
+    let mut statement_start = 0;
+    let mut chars = css.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+      if let Some(q) = quote {
+        minified.push(c);
+        if c == '\\' {
+          minified.extend(chars.next());
+        } else if c == q {
+          quote = None;
+        }
+        continue;
+      }
+
+      match c {
+        '"' | '\'' => {
+          quote = Some(c);
+          minified.push(c);
+        }
+        c if c.is_whitespace() => {
+          while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+          }
+          let previous = minified.chars().last();
+          let next = chars.peek().copied();
+          let in_declarations = blocks.last() == Some(&true);
+          let droppable = previous.is_none_or(|p| "{};,>(".contains(p) || (in_declarations && p == ':'))
+            || next.is_none_or(|n| "{};,>)!".contains(n));
+          if !droppable {
+            minified.push(' ');
+          }
+        }
+        '{' => {
+          let prelude = minified[statement_start..].trim();
+          blocks.push(!Self::has_nested_rules(prelude));
+          minified.push(c);
+          statement_start = minified.len();
+        }
+        '}' => {
+          if minified.ends_with(';') {
+            minified.pop();
+          }
+          blocks.pop();
+          minified.push(c);
+          statement_start = minified.len();
+        }
+        ';' => {
+          minified.push(c);
+          statement_start = minified.len();
+        }
+        c => minified.push(c),
+      }
+    }
+
+    minified
+  }
+
+  fn selector_matches(selector: &str, elements: &HashSet<String>, classes: &HashSet<String>, ids: &HashSet<String>) -> bool {
+    // pseudo-classes, pseudo-elements and attribute selectors are ignored, so
+    // this errs on the side of keeping rules
+    let selector = Regex::new(r"::?[a-zA-Z-]+(\([^)]*\))?|\[[^\]]*\]").unwrap().replace_all(selector, "");
+    selector.split(|c: char| c.is_whitespace() || c == '>' || c == '+' || c == '~')
+      .filter(|compound| !compound.is_empty())
+      .all(|compound| {
+        let mut qualifiers = compound.match_indices(['.', '#']).map(|(i, _)| i).peekable();
+        let element = compound[..qualifiers.peek().copied().unwrap_or(compound.len())].to_lowercase();
+        let element_matches = element.is_empty() || element == "*" || elements.contains(&element);
+
+        let qualifier_starts: Vec<usize> = qualifiers.collect();
+        element_matches && qualifier_starts.iter().enumerate().all(|(n, &start)| {
+          let end = qualifier_starts.get(n + 1).copied().unwrap_or(compound.len());
+          let name = &compound[start + 1..end];
+          match &compound[start..start + 1] {
+            "." => classes.contains(name),
+            _ => ids.contains(name),
+          }
+        })
+      })
+  }
+
+  fn critical_nodes(nodes: Vec<CssNode>, elements: &HashSet<String>, classes: &HashSet<String>, ids: &HashSet<String>) -> Vec<CssNode> {
+    nodes.into_iter()
+      .filter_map(|node| match node {
+        CssNode::Rule { prelude, block } if Self::has_nested_rules(&prelude) => {
+          let nested = Self::critical_nodes(Self::parse(&block), elements, classes, ids);
+          (!nested.is_empty()).then(|| CssNode::Rule { prelude, block: Self::format(&nested) })
+        }
+        CssNode::Rule { prelude, block } if prelude.starts_with('@') => Some(CssNode::Rule { prelude, block }),
+        CssNode::Rule { prelude, block } => prelude.split(',')
+          .any(|selector| Self::selector_matches(selector.trim(), elements, classes, ids))
+          .then_some(CssNode::Rule { prelude, block }),
+        statement => Some(statement),
+      })
+      .collect()
+  }
+
+  // approximates the critical CSS for a page as the rules whose selectors can
+  // match the elements, classes and ids that appear in its markup
+  pub fn critical(css: &str, html: &str) -> String {
+    let elements: HashSet<String> = Self::element_regex().captures_iter(html).map(|c| c[1].to_lowercase()).collect();
+    let classes: HashSet<String> = Self::class_regex().captures_iter(html)
+      .flat_map(|c| c[1].split_whitespace().map(String::from).collect::<Vec<String>>())
+      .collect();
+    let ids: HashSet<String> = Self::id_regex().captures_iter(html).map(|c| String::from(&c[1])).collect();
+
+    Self::format(&Self::critical_nodes(Self::parse(css), &elements, &classes, &ids))
+  }
+
+  // rewrites a page so its stylesheets are loaded from a single bundle and,
+  // optionally, the rules it needs up front are inlined into the head
+  fn process_page(html: &str, base: &str, stylesheets: &HashMap<String, String>, bundles: &mut BTreeMap<String, String>, options: &SiteCssOptions) -> String {
+    let mut linked: Vec<String> = vec![];
+    let mut html_without_links = String::with_capacity(html.len());
+    let mut links_at: Option<usize> = None;
+    let mut last = 0;
+    for link in Self::stylesheet_link_regex().find_iter(html) {
+      let tag = link.as_str();
+      let resource = SiteXml::attribute(tag, "href")
+        .and_then(|href| SiteRoute::resolve_reference(base, &href))
+        .filter(|resource| stylesheets.contains_key(resource));
+      let Some(resource) = resource else { continue };
+      if SiteXml::attribute(tag, "rel").as_deref() != Some("stylesheet") || SiteXml::attribute(tag, "media").is_some() {
+        continue;
+      }
+
+      html_without_links.push_str(&html[last..link.start()]);
+      links_at.get_or_insert(html_without_links.len());
+      linked.push(resource);
+      last = link.end();
+    }
+    html_without_links.push_str(&html[last..]);
+    let mut html = html_without_links;
+
+    let Some(links_at) = links_at else {
+      return html;
+    };
+
+    let hrefs: Vec<String> = if options.bundle && linked.len() > 1 {
+      // named from the stylesheets in order, since joining their names could
+      // give two combinations, or a combination and a real stylesheet, the
+      // same name
+      let resource = format!("/bundle-{}.css", SiteHash::short_hex(linked.join("\n").as_bytes()));
+      bundles.entry(resource.clone())
+        .or_insert_with(|| linked.iter().map(|r| stylesheets[r].as_str()).collect::<Vec<&str>>().join("\n"));
+      vec![resource]
+    } else {
+      linked.clone()
+    };
+    let links: String = hrefs.iter().map(|href| format!("<link rel=\"stylesheet\" href=\"{}\">", href)).collect();
+
+    if options.inline_critical {
+      let css = linked.iter().map(|r| stylesheets[r].as_str()).collect::<Vec<&str>>().join("\n");
+      let mut critical = Self::critical(&css, &html);
+      if options.minify {
+        critical = Self::minify(&critical);
+      }
+      // the full stylesheets are still loaded, just without blocking the first
+      // render, and without scripts they are linked as usual
+      let deferred: String = hrefs.iter()
+        .map(|href| format!("<link rel=\"stylesheet\" href=\"{}\" media=\"print\" data-deferred>", href))
+        .collect();
+      html.insert_str(links_at, &format!("<style>{}</style>{}<noscript>{}</noscript>", critical, deferred, links));
+      match html.rfind("</body>") {
+        Some(i) => html.insert_str(i, Self::DEFERRED_LOADER),
+        None => html.push_str(Self::DEFERRED_LOADER),
+      }
+    } else {
+      html.insert_str(links_at, &links);
+    }
+    html
+  }
+
+  // inlines imports in every stylesheet, minifies them, and bundles the
+  // stylesheets each compiled page links to into one file per combination
+  pub fn process(dist: &Path, routes: &mut Vec<SiteRoute>, options: &SiteCssOptions) -> io::Result<()> {
+    let mut sources: HashMap<String, String> = HashMap::new();
+    for route in routes.iter().filter(|route| route.file.ends_with(".css")) {
+      sources.insert(route.resource.clone(), fs::read_to_string(dist.join(&route.file))?);
+    }
+
+    let load = |resource: &str| sources.get(resource).cloned();
+    let mut stylesheets: HashMap<String, String> = HashMap::new();
+    for route in routes.iter().filter(|route| sources.contains_key(&route.resource)) {
+      let mut css = Self::resolve_imports(&sources[&route.resource], &route.resource, &load);
+      if options.minify {
+        css = Self::minify(&css);
+      }
+      fs::write(dist.join(&route.file), &css)?;
+      stylesheets.insert(route.resource.clone(), css);
+    }
+
+    let mut bundles: BTreeMap<String, String> = BTreeMap::new();
+    for entry in fs::read_dir(dist)? {
+      let path = entry?.path();
+      if path.extension().and_then(|e| e.to_str()) != Some("html") {
+        continue;
+      }
+
+      let name = path.file_name().expect("Page does not have filename.").to_string_lossy().to_string();
+      let base = routes.iter()
+        .find(|route| route.file == name)
+        .map(|route| route.resource.clone())
+        .unwrap_or_else(|| String::from("/"));
+      let html = fs::read_to_string(&path)?;
+      fs::write(&path, Self::process_page(&html, &base, &stylesheets, &mut bundles, options))?;
+    }
+
+    for (resource, css) in bundles {
+      let file = resource.trim_start_matches('/');
+      if routes.iter().any(|route| route.resource == resource || route.file == file) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("CSS bundle {} has the same name as a static file", resource)));
+      }
+      fs::write(dist.join(file), css)?;
+      routes.push(SiteRoute::new(&resource, file, None));
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn page(stylesheets: &[&str]) -> String {
+    let links: String = stylesheets.iter().map(|href| format!("<link rel=\"stylesheet\" href=\"{}\">", href)).collect();
+    format!("<html><head>{}</head><body></body></html>", links)
+  }
+
+  #[test]
+  fn combinations_with_the_same_joined_names_get_their_own_bundles() {
+    let stylesheets: HashMap<String, String> = ["/a.css", "/b-c.css", "/a-b.css", "/c.css"]
+      .iter()
+      .map(|resource| (String::from(*resource), format!("/* {} */", resource)))
+      .collect();
+    let mut bundles = BTreeMap::new();
+    let options = SiteCssOptions::default();
+    let first = SiteCss::process_page(&page(&["/a.css", "/b-c.css"]), "/", &stylesheets, &mut bundles, &options);
+    let second = SiteCss::process_page(&page(&["/a-b.css", "/c.css"]), "/", &stylesheets, &mut bundles, &options);
+
+    assert_ne!(first, second);
+    assert_eq!(bundles.len(), 2);
+    assert!(bundles.values().any(|css| css == "/* /a.css */\n/* /b-c.css */"));
+    assert!(bundles.values().any(|css| css == "/* /a-b.css */\n/* /c.css */"));
+    assert!(bundles.keys().all(|resource| resource.starts_with("/bundle-")));
+  }
+
+  #[test]
+  fn the_same_combination_shares_a_bundle() {
+    let stylesheets: HashMap<String, String> = ["/a.css", "/b.css"]
+      .iter()
+      .map(|resource| (String::from(*resource), String::new()))
+      .collect();
+    let mut bundles = BTreeMap::new();
+    let options = SiteCssOptions::default();
+    let first = SiteCss::process_page(&page(&["/a.css", "/b.css"]), "/", &stylesheets, &mut bundles, &options);
+    let second = SiteCss::process_page(&page(&["/a.css", "/b.css"]), "/", &stylesheets, &mut bundles, &options);
+
+    assert_eq!(first, second);
+    assert_eq!(bundles.len(), 1);
+  }
+}
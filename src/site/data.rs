@@ -0,0 +1,63 @@
+use serde_json::Value;
+
+use crate::site::header::SiteHeader;
+
+#[derive(Debug)]
+pub struct SiteData {
+  pub name: String,
+  pub value: Value,
+}
+
+impl SiteData {
+  fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+  }
+
+  // resolves a dotted path such as "experience.jobs", first against the
+  // current loop item (for nested loops) and then against the data files
+  pub fn resolve<'a>(data: &'a [SiteData], context: Option<&'a Value>, path: &str) -> Option<&'a Value> {
+    let path: Vec<&str> = path.split('.').collect();
+    context
+      .and_then(|context| Self::lookup(context, &path))
+      .or_else(|| {
+        let (name, rest) = path.split_first()?;
+        data.iter()
+          .find(|d| d.name == *name)
+          .and_then(|d| Self::lookup(&d.value, rest))
+      })
+  }
+
+  fn format_scalar(value: &Value) -> Option<String> {
+    match value {
+      Value::String(s) => Some(s.clone()),
+      Value::Number(n) => Some(n.to_string()),
+      Value::Bool(b) => Some(b.to_string()),
+      _ => None,
+    }
+  }
+
+  // scalar fields become headers directly and lists of scalars become <li>
+  // items; nested objects are only reachable through a nested loop
+  pub fn to_headers(item: &Value) -> Vec<SiteHeader> {
+    let Value::Object(fields) = item else {
+      return Self::format_scalar(item)
+        .map(|value| vec![SiteHeader { name: String::from("item"), value }])
+        .unwrap_or_default();
+    };
+
+    fields.iter()
+      .filter_map(|(name, value)| {
+        let value = match value {
+          Value::Array(items) => items.iter()
+            .map(Self::format_scalar)
+            .collect::<Option<Vec<String>>>()?
+            .iter()
+            .map(|item| format!("<li>{}</li>", item))
+            .collect(),
+          _ => Self::format_scalar(value)?,
+        };
+        Some(SiteHeader { name: name.clone(), value })
+      })
+      .collect()
+  }
+}
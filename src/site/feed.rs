@@ -0,0 +1,181 @@
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use regex::{Captures, Regex};
+
+use crate::site::{post::SitePost, xml::SiteXml};
+
+pub struct SiteFeed {
+  pub title: String,
+  pub description: String,
+  pub author: String,
+  pub base_url: String,
+  pub include_content: bool,
+}
+
+impl SiteFeed {
+  pub const ATOM_FILE: &str = "feed.xml";
+  pub const RSS_FILE: &str = "rss.xml";
+  pub const ATOM_CONTENT_TYPE: &str = "application/atom+xml";
+  pub const RSS_CONTENT_TYPE: &str = "application/rss+xml";
+
+  fn to_datetime(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).expect("Midnight is a valid time").and_utc()
+  }
+
+  fn rfc3339(date: NaiveDate) -> String {
+    Self::to_datetime(date).to_rfc3339_opts(SecondsFormat::Secs, true)
+  }
+
+  fn rfc2822(date: NaiveDate) -> String {
+    Self::to_datetime(date).to_rfc2822()
+  }
+
+  fn last_updated(posts: &[SitePost]) -> NaiveDate {
+    posts.iter()
+      .map(|p| p.updated)
+      .max()
+      .unwrap_or_else(|| Utc::now().date_naive())
+  }
+
+  fn absolute_url(&self, path: &str) -> String {
+    format!("{}{}", self.base_url.trim_end_matches('/'), path)
+  }
+
+  fn absolute_reference(&self, reference: &str) -> String {
+    let trimmed = reference.trim_start();
+    if trimmed.starts_with('/') && !trimmed.starts_with("//") {
+      format!("{}{}", &reference[..reference.len() - trimmed.len()], self.absolute_url(trimmed))
+    } else {
+      String::from(reference)
+    }
+  }
+
+  // root-relative links and sources in post content are made absolute, since
+  // feed readers show the content away from the site
+  fn absolute_content(&self, html: &str) -> String {
+    let attribute_regex = Regex::new(r#"(?i)(\s(href|src|srcset)\s*=\s*)("[^"]*"|'[^']*')"#).unwrap();
+    attribute_regex.replace_all(html, |c: &Captures| {
+      let quoted = &c[3];
+      let (quote, value) = (&quoted[..1], &quoted[1..quoted.len() - 1]);
+      let value = if c[2].eq_ignore_ascii_case("srcset") {
+        value.split(',').map(|candidate| self.absolute_reference(candidate)).collect::<Vec<String>>().join(",")
+      } else {
+        self.absolute_reference(value)
+      };
+      format!("{}{}{}{}", &c[1], quote, value, quote)
+    }).into_owned()
+  }
+
+  fn atom_entry(&self, post: &SitePost) -> String {
+    let url = self.absolute_url(&post.url);
+    [
+      String::from("<entry>"),
+      SiteXml::element("title", &post.title),
+      format!("<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>", SiteXml::escape(&url)),
+      SiteXml::element("id", &url),
+      SiteXml::element("published", &Self::rfc3339(post.published)),
+      SiteXml::element("updated", &Self::rfc3339(post.updated)),
+      format!("<summary type=\"html\">{}</summary>", SiteXml::escape(&post.summary)),
+      post.tags.iter().map(|tag| format!("<category term=\"{}\"/>", SiteXml::escape(tag))).collect(),
+      if self.include_content {
+        format!("<content type=\"html\">{}</content>", SiteXml::escape(&self.absolute_content(&post.content)))
+      } else {
+        String::new()
+      },
+      String::from("</entry>"),
+    ].concat()
+  }
+
+  fn rss_item(&self, post: &SitePost) -> String {
+    let url = self.absolute_url(&post.url);
+    [
+      String::from("<item>"),
+      SiteXml::element("title", &post.title),
+      SiteXml::element("link", &url),
+      format!("<guid isPermaLink=\"true\">{}</guid>", SiteXml::escape(&url)),
+      SiteXml::element("pubDate", &Self::rfc2822(post.published)),
+      SiteXml::element("description", &post.summary),
+      post.tags.iter().map(|tag| SiteXml::element("category", tag)).collect(),
+      if self.include_content {
+        SiteXml::element("content:encoded", &self.absolute_content(&post.content))
+      } else {
+        String::new()
+      },
+      String::from("</item>"),
+    ].concat()
+  }
+
+  pub fn atom(&self, posts: &[SitePost]) -> String {
+    let home_url = self.absolute_url("/");
+    [
+      String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n"),
+      String::from("<feed xmlns=\"http://www.w3.org/2005/Atom\">"),
+      SiteXml::element("title", &self.title),
+      SiteXml::element("subtitle", &self.description),
+      format!("<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>", SiteXml::escape(&home_url)),
+      format!(
+        "<link rel=\"self\" type=\"{}\" href=\"{}\"/>",
+        Self::ATOM_CONTENT_TYPE,
+        SiteXml::escape(&self.absolute_url(&format!("/{}", Self::ATOM_FILE)))
+      ),
+      SiteXml::element("id", &home_url),
+      SiteXml::element("updated", &Self::rfc3339(Self::last_updated(posts))),
+      format!("<author>{}</author>", SiteXml::element("name", &self.author)),
+      posts.iter().map(|p| self.atom_entry(p)).collect(),
+      String::from("</feed>\n"),
+    ].concat()
+  }
+
+  pub fn rss(&self, posts: &[SitePost]) -> String {
+    [
+      String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n"),
+      String::from("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:content=\"http://purl.org/rss/1.0/modules/content/\">"),
+      String::from("<channel>"),
+      SiteXml::element("title", &self.title),
+      SiteXml::element("link", &self.absolute_url("/")),
+      SiteXml::element("description", &self.description),
+      format!(
+        "<atom:link rel=\"self\" type=\"{}\" href=\"{}\"/>",
+        Self::RSS_CONTENT_TYPE,
+        SiteXml::escape(&self.absolute_url(&format!("/{}", Self::RSS_FILE)))
+      ),
+      SiteXml::element("lastBuildDate", &Self::rfc2822(Self::last_updated(posts))),
+      posts.iter().map(|p| self.rss_item(p)).collect(),
+      String::from("</channel>"),
+      String::from("</rss>\n"),
+    ].concat()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn feed() -> SiteFeed {
+    SiteFeed {
+      title: String::from("Site"),
+      description: String::new(),
+      author: String::new(),
+      base_url: String::from("https://example.com/"),
+      include_content: true,
+    }
+  }
+
+  #[test]
+  fn root_relative_references_are_made_absolute() {
+    let html = "<a href=\"/blog/post\">post</a><img src='/images/a.png' alt=\"\">";
+    assert_eq!(feed().absolute_content(html), "<a href=\"https://example.com/blog/post\">post</a><img src='https://example.com/images/a.png' alt=\"\">");
+  }
+
+  #[test]
+  fn every_srcset_candidate_is_made_absolute() {
+    let html = "<source srcset=\"/a-320.webp 320w, /a-640.webp 640w\">";
+    assert_eq!(feed().absolute_content(html), "<source srcset=\"https://example.com/a-320.webp 320w, https://example.com/a-640.webp 640w\">");
+  }
+
+  #[test]
+  fn other_references_are_left_alone() {
+    let html = "<a href=\"https://other.com/\">a</a><a href=\"#top\">b</a><img src=\"//cdn.example.com/a.png\"><a href=\"/x,y\">c</a>";
+    let expected = "<a href=\"https://other.com/\">a</a><a href=\"#top\">b</a><img src=\"//cdn.example.com/a.png\"><a href=\"https://example.com/x,y\">c</a>";
+    assert_eq!(feed().absolute_content(html), expected);
+  }
+}
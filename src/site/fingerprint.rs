@@ -0,0 +1,163 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use regex::{Captures, Regex};
+
+use crate::site::{hash::SiteHash, route::SiteRoute};
+
+pub struct SiteFingerprinter;
+impl SiteFingerprinter {
+  // the renamed resources, one per line, which the server caches for good
+  pub const FILE: &str = "fingerprints.txt";
+  const ASSET_EXTENSIONS: [&str; 13] = [
+    "css", "js", "png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "ico", "woff", "woff2", "ttf",
+  ];
+
+  fn html_reference_regex() -> Regex {
+    Regex::new(r#"\b(href|src)="([^"]*)""#).unwrap()
+  }
+
+  fn srcset_regex() -> Regex {
+    Regex::new(r#"\bsrcset="([^"]*)""#).unwrap()
+  }
+
+  fn css_reference_regex() -> Regex {
+    Regex::new(r#"url\(\s*(['"]?)([^'")]*)(['"]?)\s*\)|@import\s+(['"])([^'"]*)(['"])"#).unwrap()
+  }
+
+  fn extension(file: &str) -> Option<&str> {
+    Path::new(file).extension().and_then(|e| e.to_str())
+  }
+
+  fn is_asset(route: &SiteRoute) -> bool {
+    Self::extension(&route.file).is_some_and(|e| Self::ASSET_EXTENSIONS.contains(&e))
+  }
+
+  fn is_css(route: &SiteRoute) -> bool {
+    Self::extension(&route.file) == Some("css")
+  }
+
+  fn with_hash(name: &str, hash: &str) -> String {
+    match name.rsplit_once('.') {
+      Some((stem, extension)) if !stem.ends_with('/') => format!("{}.{}.{}", stem, hash, extension),
+      _ => format!("{}.{}", name, hash),
+    }
+  }
+
+  fn rewrite_reference(base: &str, reference: &str, renamed: &HashMap<String, String>) -> String {
+    SiteRoute::resolve_reference(base, reference)
+      .and_then(|resource| renamed.get(&resource))
+      .map(|fingerprinted| {
+        // keep any query string or fragment the author added
+        let suffix = reference.find(['?', '#']).map(|i| &reference[i..]).unwrap_or_default();
+        format!("{}{}", fingerprinted, suffix)
+      })
+      .unwrap_or_else(|| String::from(reference))
+  }
+
+  fn rewrite_html(html: &str, base: &str, renamed: &HashMap<String, String>) -> String {
+    let html = Self::html_reference_regex()
+      .replace_all(html, |c: &Captures| {
+        format!("{}=\"{}\"", &c[1], Self::rewrite_reference(base, &c[2], renamed))
+      })
+      .into_owned();
+
+    // each srcset candidate is a url optionally followed by a descriptor
+    Self::srcset_regex()
+      .replace_all(&html, |c: &Captures| {
+        let candidates: Vec<String> = c[1].split(',')
+          .map(|candidate| {
+            let candidate = candidate.trim();
+            match candidate.split_once(char::is_whitespace) {
+              Some((reference, descriptor)) => format!("{} {}", Self::rewrite_reference(base, reference, renamed), descriptor.trim()),
+              None => Self::rewrite_reference(base, candidate, renamed),
+            }
+          })
+          .collect();
+        format!("srcset=\"{}\"", candidates.join(", "))
+      })
+      .into_owned()
+  }
+
+  fn rewrite_css(css: &str, base: &str, renamed: &HashMap<String, String>) -> String {
+    Self::css_reference_regex()
+      .replace_all(css, |c: &Captures| match c.get(2) {
+        Some(reference) => format!("url({}{}{})", &c[1], Self::rewrite_reference(base, reference.as_str(), renamed), &c[3]),
+        None => format!("@import {}{}{}", &c[4], Self::rewrite_reference(base, &c[5], renamed), &c[6]),
+      })
+      .into_owned()
+  }
+
+  fn fingerprint_route(dist: &Path, route: &SiteRoute, file_hashes: &mut HashMap<String, String>) -> io::Result<SiteRoute> {
+    let hash = match file_hashes.get(&route.file) {
+      Some(hash) => hash.clone(),
+      None => {
+        let hash = SiteHash::short_hex(&fs::read(dist.join(&route.file))?);
+        fs::rename(dist.join(&route.file), dist.join(Self::with_hash(&route.file, &hash)))?;
+        file_hashes.insert(route.file.clone(), hash.clone());
+        hash
+      }
+    };
+
+    Ok(SiteRoute {
+      resource: Self::with_hash(&route.resource, &hash),
+      file: Self::with_hash(&route.file, &hash),
+      content_type: route.content_type.clone(),
+    })
+  }
+
+  // renames every static asset to include a hash of its contents, rewrites
+  // references to them in the compiled HTML and CSS, and adds routes for the
+  // new names (the original resources keep resolving to the renamed files),
+  // which are also listed in `FILE`
+  pub fn fingerprint(dist: &Path, routes: &mut Vec<SiteRoute>) -> io::Result<()> {
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    let mut file_hashes: HashMap<String, String> = HashMap::new();
+    let mut fingerprinted: Vec<SiteRoute> = vec![];
+
+    // CSS is done last since its hash has to cover the rewritten references
+    let (css, other): (Vec<SiteRoute>, Vec<SiteRoute>) = routes.iter()
+      .filter(|route| Self::is_asset(route))
+      .cloned()
+      .partition(Self::is_css);
+
+    for route in &other {
+      let new_route = Self::fingerprint_route(dist, route, &mut file_hashes)?;
+      renamed.insert(route.resource.clone(), new_route.resource.clone());
+      fingerprinted.push(new_route);
+    }
+
+    for route in &css {
+      if !file_hashes.contains_key(&route.file) {
+        let path = dist.join(&route.file);
+        fs::write(&path, Self::rewrite_css(&fs::read_to_string(&path)?, &route.resource, &renamed))?;
+      }
+      let new_route = Self::fingerprint_route(dist, route, &mut file_hashes)?;
+      renamed.insert(route.resource.clone(), new_route.resource.clone());
+      fingerprinted.push(new_route);
+    }
+
+    for entry in fs::read_dir(dist)? {
+      let path = entry?.path();
+      if path.extension().and_then(|e| e.to_str()) != Some("html") {
+        continue;
+      }
+
+      let name = path.file_name().expect("Page does not have filename.").to_string_lossy().to_string();
+      let base = routes.iter()
+        .find(|route| route.file == name)
+        .map(|route| route.resource.clone())
+        .unwrap_or_else(|| String::from("/"));
+      fs::write(&path, Self::rewrite_html(&fs::read_to_string(&path)?, &base, &renamed))?;
+    }
+
+    for route in routes.iter_mut() {
+      if let Some(hash) = file_hashes.get(&route.file) {
+        route.file = Self::with_hash(&route.file, hash);
+      }
+    }
+    fs::write(dist.join(Self::FILE), fingerprinted.iter().map(|route| format!("{}\n", route.resource)).collect::<String>())?;
+    routes.append(&mut fingerprinted);
+
+    Ok(())
+  }
+}
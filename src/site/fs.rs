@@ -1,12 +1,14 @@
 use std::{fs, io, path::{Path, PathBuf}, rc::Rc};
 
-use crate::site::{component::SiteComponent, parser::SiteParser, template::SiteTemplate};
+use serde_json::Value;
+
+use crate::site::{component::SiteComponent, data::SiteData, parser::SiteParser, route::SiteRoute, template::SiteTemplate};
 
 pub struct SiteFs;
 impl SiteFs {
 
   // from https://stackoverflow.com/a/76820878
-  fn read_all_files(path: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+  pub fn read_all_files(path: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
     let mut buf = vec![];
     let entries = fs::read_dir(path)?;
 
@@ -27,35 +29,77 @@ impl SiteFs {
     Ok(buf)
   }
 
-  pub fn read_templates(dir: &str, components: Rc<[SiteComponent]>) -> Rc<[SiteTemplate]> {
-    Self::read_all_files(String::from(dir))
+  pub fn read_templates(dir: impl AsRef<Path>, components: Rc<[SiteComponent]>, data: Rc<[SiteData]>) -> Rc<[SiteTemplate]> {
+    Self::read_all_files(dir)
         .expect("Could not read files from directory.")
         .iter()
         .map(|path| {
             SiteParser::parse_template_from_string(
-                &fs::read_to_string(&path)?,
-                &path.file_name().expect("Page does not have filename.").to_string_lossy(),
-                components.clone()
+                &fs::read_to_string(path)?,
+                path,
+                components.clone(),
+                data.clone()
             )
         })
         .filter_map(|res| res.ok())
         .collect()
   }
 
-  pub fn read_components(dir: &str) -> Rc<[SiteComponent]> {
-      Self::read_all_files(String::from(dir))
+  pub fn read_components(dir: impl AsRef<Path>) -> Rc<[SiteComponent]> {
+      Self::read_all_files(dir)
           .expect("Could not read files from directory.")
           .iter()
           .map(|e| {
               Ok(SiteComponent {
                   name: String::from(e.file_name().expect("Component does not have filename.").to_string_lossy().trim_end_matches(".html")),
-                  body: fs::read_to_string(e)?.lines().map(|l| l.trim()).collect()
+                  body: String::from(fs::read_to_string(e)?.trim())
               }) as io::Result<SiteComponent>
           })
           .filter_map(|res| res.ok())
           .collect()
   }
 
+  fn parse_data_file(path: &Path) -> io::Result<Option<Value>> {
+    let content = fs::read_to_string(path)?;
+    let value = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(io::Error::other)?,
+        Some("toml") => toml::from_str(&content).map_err(io::Error::other)?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(io::Error::other)?,
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+  }
+
+  pub fn read_data(dir: impl AsRef<Path>) -> io::Result<Rc<[SiteData]>> {
+    if !fs::exists(&dir)? {
+        return Ok(Rc::new([]));
+    }
+
+    let mut data = vec![];
+    for path in Self::read_all_files(dir)? {
+        if let Some(value) = Self::parse_data_file(&path)? {
+            data.push(SiteData {
+                name: path.file_stem().expect("Data file does not have filename.").to_string_lossy().to_string(),
+                value,
+            });
+        }
+    }
+    Ok(data.into())
+  }
+
+  pub fn read_routes(path: impl AsRef<Path>) -> io::Result<Vec<SiteRoute>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(SiteRoute::parse)
+        .collect())
+  }
+
+  pub fn write_routes(path: impl AsRef<Path>, routes: &[SiteRoute]) -> io::Result<()> {
+    fs::write(path, routes.iter()
+        .map(|route| format!("{}\n", route.format()))
+        .collect::<String>())
+  }
+
   pub fn delete_dir_if_exists(path: impl AsRef<Path>) -> io::Result<()> {
     if fs::exists(&path)? {
         fs::remove_dir_all(&path)?
@@ -77,4 +121,34 @@ impl SiteFs {
       }
       Ok(())
   }
-}
\ No newline at end of file
+
+  // makes dst match src, only writing files whose contents differ so that
+  // unchanged outputs keep their modification times
+  pub fn sync_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+      fs::create_dir_all(&dst)?;
+      for entry in fs::read_dir(&dst)? {
+          let entry = entry?;
+          let source = src.as_ref().join(entry.file_name());
+          let is_dir = entry.file_type()?.is_dir();
+          if source.exists() && source.is_dir() == is_dir {
+              continue;
+          }
+          if is_dir {
+              fs::remove_dir_all(entry.path())?;
+          } else {
+              fs::remove_file(entry.path())?;
+          }
+      }
+
+      for entry in fs::read_dir(&src)? {
+          let entry = entry?;
+          let target = dst.as_ref().join(entry.file_name());
+          if entry.file_type()?.is_dir() {
+              Self::sync_dir(entry.path(), target)?;
+          } else if fs::read(&target).ok() != Some(fs::read(entry.path())?) {
+              fs::copy(entry.path(), target)?;
+          }
+      }
+      Ok(())
+  }
+}
@@ -0,0 +1,86 @@
+use std::{collections::{BTreeSet, HashMap}, fs, path::{Path, PathBuf}};
+
+use regex::Regex;
+
+use crate::site::hash::SiteHash;
+
+// nodes are source files under content/ and the files they compile to under
+// dist/; an edge points from a file to something it is built from
+#[derive(Debug, Default)]
+pub struct SiteDependencyGraph {
+  dependencies: HashMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+impl SiteDependencyGraph {
+  fn template_regex() -> Regex {
+    Regex::new(r"\[template=([^\[\]]+)\]").unwrap()
+  }
+
+  fn component_regex() -> Regex {
+    Regex::new(r"\[\[([a-z0-9_]+)\]\]").unwrap()
+  }
+
+  fn each_regex() -> Regex {
+    Regex::new(r"\[each=([^\[\]]+)\]").unwrap()
+  }
+
+  pub fn add(&mut self, dependent: &Path, dependency: &Path) {
+    self.dependencies.entry(dependent.to_path_buf()).or_default().insert(dependency.to_path_buf());
+  }
+
+  // records what a template, page or component source references: its parent
+  // template, the components it invokes and the data files it loops over
+  pub fn add_source(&mut self, path: &Path, content: &str, templates: &Path, components: &Path, data: &[PathBuf]) {
+    for c in Self::template_regex().captures_iter(content) {
+      self.add(path, &templates.join(c[1].trim()));
+    }
+    for c in Self::component_regex().captures_iter(content) {
+      self.add(path, &components.join(format!("{}.html", &c[1])));
+    }
+    for c in Self::each_regex().captures_iter(content) {
+      let name = c[1].trim().split('.').next().unwrap_or_default();
+      for data_path in data.iter().filter(|p| p.file_stem().is_some_and(|stem| stem == name)) {
+        self.add(path, data_path);
+      }
+    }
+  }
+
+  fn walk(edges: &HashMap<PathBuf, BTreeSet<PathBuf>>, node: &Path) -> BTreeSet<PathBuf> {
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![node.to_path_buf()];
+    while let Some(current) = stack.pop() {
+      for next in edges.get(&current).into_iter().flatten() {
+        if visited.insert(next.clone()) {
+          stack.push(next.clone());
+        }
+      }
+    }
+    visited.remove(node);
+    visited
+  }
+
+  pub fn dependencies(&self, node: &Path) -> BTreeSet<PathBuf> {
+    Self::walk(&self.dependencies, node)
+  }
+
+  pub fn dependents(&self, node: &Path) -> BTreeSet<PathBuf> {
+    let mut reversed: HashMap<PathBuf, BTreeSet<PathBuf>> = HashMap::new();
+    for (dependent, dependencies) in &self.dependencies {
+      for dependency in dependencies {
+        reversed.entry(dependency.clone()).or_default().insert(dependent.clone());
+      }
+    }
+    Self::walk(&reversed, node)
+  }
+
+  // hashes the contents of everything a node is built from, so the result
+  // only changes when one of its inputs does
+  pub fn input_hash(&self, node: &Path, salt: &str) -> String {
+    let mut inputs = String::from(salt);
+    for dependency in self.dependencies(node) {
+      let hash = fs::read(&dependency).map(|bytes| SiteHash::short_hex(&bytes)).unwrap_or_default();
+      inputs.push_str(&format!("\n{} {}", dependency.display(), hash));
+    }
+    SiteHash::short_hex(inputs.as_bytes())
+  }
+}
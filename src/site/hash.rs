@@ -0,0 +1,31 @@
+pub struct SiteHash;
+impl SiteHash {
+  const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  // 64 bits of sha256; fingerprinted assets are cached as immutable for a
+  // year, so two versions of one asset sharing a name would be a stale file
+  // nobody can bust
+  const SHORT_HEX_BYTES: usize = 8;
+
+  pub fn short_hex(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes).as_ref()[..Self::SHORT_HEX_BYTES]
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect()
+  }
+
+  // standard base64 with padding, the form CSP hash sources are written in
+  fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+      let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+      for i in 0..4 {
+        out.push(if i <= chunk.len() { Self::BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char } else { '=' });
+      }
+    }
+    out
+  }
+
+  pub fn sha256_base64(bytes: &[u8]) -> String {
+    Self::base64(ring::digest::digest(&ring::digest::SHA256, bytes).as_ref())
+  }
+}
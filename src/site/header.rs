@@ -3,3 +3,12 @@ pub struct SiteHeader {
   pub name: String,
   pub value: String,
 }
+
+impl SiteHeader {
+  pub fn get_header(headers: &[SiteHeader], header_name: &str) -> Option<String> {
+    headers
+      .iter()
+      .find(|h| h.name == header_name)
+      .map(|h| h.value.trim().to_string())
+  }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+
+use regex::{Captures, Regex};
+
+use crate::site::parser::SiteParser;
+
+#[derive(Debug, Clone)]
+pub struct SiteHeading {
+  pub level: u8,
+  pub id: String,
+  pub text: String,
+}
+
+pub struct SiteHeadings;
+impl SiteHeadings {
+  const TOC_MIN_LEVEL: u8 = 2;
+
+  fn heading_regex() -> Regex {
+    Regex::new(r"(?s)<h([1-6])([^>]*)>(.*?)</h[1-6]>").unwrap()
+  }
+
+  fn id_regex() -> Regex {
+    Regex::new(r#"\bid="([^"]*)""#).unwrap()
+  }
+
+  fn tag_regex() -> Regex {
+    Regex::new(r"<[^>]+>").unwrap()
+  }
+
+  fn unique_id(text: &str, used: &mut HashSet<String>) -> String {
+    let slug = match SiteParser::slugify(text) {
+      slug if slug.is_empty() => String::from("section"),
+      slug => slug,
+    };
+
+    let mut id = slug.clone();
+    let mut n = 1;
+    while used.contains(&id) {
+      id = format!("{}-{}", slug, n);
+      n += 1;
+    }
+    used.insert(id.clone());
+    id
+  }
+
+  // gives every heading in the body an id, keeping any the author already set;
+  // self links go on section headings only, since an h1 is the page title
+  pub fn assign_ids(body: &str, self_links: bool) -> (String, Vec<SiteHeading>) {
+    let mut used: HashSet<String> = Self::id_regex()
+      .captures_iter(body)
+      .map(|c| String::from(&c[1]))
+      .collect();
+    let mut headings = vec![];
+
+    let body = Self::heading_regex().replace_all(body, |c: &Captures| {
+      let level: u8 = c[1].parse().expect("Heading level is a digit");
+      let text = Self::tag_regex().replace_all(&c[3], "").split_whitespace().collect::<Vec<&str>>().join(" ");
+      let (attributes, id) = match Self::id_regex().captures(&c[2]) {
+        Some(existing) => (String::from(&c[2]), String::from(&existing[1])),
+        None => {
+          let id = Self::unique_id(&text, &mut used);
+          (format!(" id=\"{}\"{}", id, &c[2]), id)
+        }
+      };
+
+      let anchor = if self_links && level >= Self::TOC_MIN_LEVEL {
+        format!(" <a class=\"heading-anchor\" href=\"#{}\" aria-label=\"Link to this section\">#</a>", id)
+      } else {
+        String::new()
+      };
+
+      headings.push(SiteHeading { level, id, text });
+      format!("<h{}{}>{}{}</h{}>", level, attributes, &c[3], anchor, level)
+    }).into_owned();
+
+    (body, headings)
+  }
+
+  pub fn table_of_contents(headings: &[SiteHeading]) -> String {
+    let mut toc = String::new();
+    let mut levels: Vec<u8> = vec![];
+
+    for heading in headings.iter().filter(|h| h.level >= Self::TOC_MIN_LEVEL) {
+      while levels.last().is_some_and(|&level| level > heading.level) {
+        toc.push_str("</li></ul>");
+        levels.pop();
+      }
+
+      if levels.last() == Some(&heading.level) {
+        toc.push_str("</li>");
+      } else {
+        toc.push_str("<ul>");
+        levels.push(heading.level);
+      }
+      toc.push_str(&format!("<li><a href=\"#{}\">{}</a>", heading.id, heading.text));
+    }
+
+    while levels.pop().is_some() {
+      toc.push_str("</li></ul>");
+    }
+    toc
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ids(headings: &[SiteHeading]) -> Vec<&str> {
+    headings.iter().map(|h| h.id.as_str()).collect()
+  }
+
+  #[test]
+  fn duplicate_headings_get_unique_ids() {
+    let body = "<h2>Setup</h2><h2>Setup</h2><h3 id=\"setup-2\">Setup</h3><h2>Setup</h2><h2>!!!</h2>";
+    let (body, headings) = SiteHeadings::assign_ids(body, false);
+    assert_eq!(ids(&headings), vec!["setup", "setup-1", "setup-2", "setup-3", "section"]);
+    assert_eq!(
+      body,
+      "<h2 id=\"setup\">Setup</h2><h2 id=\"setup-1\">Setup</h2><h3 id=\"setup-2\">Setup</h3>\
+       <h2 id=\"setup-3\">Setup</h2><h2 id=\"section\">!!!</h2>",
+    );
+  }
+
+  #[test]
+  fn markup_in_headings_is_kept_in_the_body_and_dropped_from_the_text() {
+    let body = "<h2 class=\"wide\">Using <code>Vec</code>\n  and <em>slices</em></h2>";
+    let (body, headings) = SiteHeadings::assign_ids(body, false);
+    assert_eq!(body, "<h2 id=\"using-vec-and-slices\" class=\"wide\">Using <code>Vec</code>\n  and <em>slices</em></h2>");
+    assert_eq!(headings[0].text, "Using Vec and slices");
+  }
+
+  #[test]
+  fn self_links_skip_the_page_title() {
+    let (body, _) = SiteHeadings::assign_ids("<h1>Title</h1><h2>Part</h2>", true);
+    assert_eq!(
+      body,
+      "<h1 id=\"title\">Title</h1>\
+       <h2 id=\"part\">Part <a class=\"heading-anchor\" href=\"#part\" aria-label=\"Link to this section\">#</a></h2>",
+    );
+  }
+
+  #[test]
+  fn table_of_contents_nests_by_level_and_leaves_out_the_title() {
+    let (_, headings) = SiteHeadings::assign_ids("<h1>Title</h1><h2>A</h2><h3>B</h3><h4>C</h4><h2>D</h2><h3>E</h3>", false);
+    assert_eq!(
+      SiteHeadings::table_of_contents(&headings),
+      "<ul><li><a href=\"#a\">A</a><ul><li><a href=\"#b\">B</a><ul><li><a href=\"#c\">C</a></li></ul></li></ul></li>\
+       <li><a href=\"#d\">D</a><ul><li><a href=\"#e\">E</a></li></ul></li></ul>",
+    );
+    assert_eq!(SiteHeadings::table_of_contents(&headings[..1]), "");
+  }
+}
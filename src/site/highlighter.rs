@@ -0,0 +1,312 @@
+use regex::{Captures, Regex};
+
+struct HighlightRule {
+  class: &'static str,
+  regex: Regex,
+  line_start: bool,
+}
+
+impl HighlightRule {
+  // the first capture group, when present, is the highlighted token so that
+  // a rule can require trailing context such as the "(" after a function name
+  fn new(class: &'static str, pattern: &str) -> HighlightRule {
+    HighlightRule { class, regex: Regex::new(&format!("^(?:{})", pattern)).unwrap(), line_start: false }
+  }
+
+  fn at_line_start(class: &'static str, pattern: &str) -> HighlightRule {
+    HighlightRule { line_start: true, ..Self::new(class, pattern) }
+  }
+}
+
+pub struct SiteHighlighter;
+impl SiteHighlighter {
+  pub const STYLESHEET_FILE: &str = "highlight.css";
+
+  const THEME: [(&str, &str); 11] = [
+    ("comment", "color: #6a737d; font-style: italic"),
+    ("string", "color: #22863a"),
+    ("keyword", "color: #c41e3d; font-weight: bold"),
+    ("number", "color: #005cc5"),
+    ("type", "color: #6f42c1"),
+    ("function", "color: #8a4b08"),
+    ("macro", "color: #8a4b08; font-weight: bold"),
+    ("attribute", "color: #005cc5"),
+    ("tag", "color: #c41e3d"),
+    ("variable", "color: #e36209"),
+    ("lifetime", "color: #e36209; font-style: italic"),
+  ];
+
+  fn code_block_regex() -> Regex {
+    Regex::new(r#"(?s)<pre([^>]*)>\s*<code([^>]*)>(.*?)</code>\s*</pre>"#).unwrap()
+  }
+
+  fn language_regex() -> Regex {
+    Regex::new(r#"class="[^"]*\blanguage-([a-z0-9_+-]+)"#).unwrap()
+  }
+
+  fn highlight_lines_regex() -> Regex {
+    Regex::new(r#"data-highlight="([0-9,\s-]+)""#).unwrap()
+  }
+
+  fn rules(language: &str) -> Option<Vec<HighlightRule>> {
+    let string = r#""(?:[^"\\]|\\[\s\S])*"|'(?:[^'\\\n]|\\.)*'"#;
+    let number = r"-?\b[0-9][0-9_]*(?:\.[0-9]+)?(?:[eE][+-]?[0-9]+)?\b";
+
+    let mut rules = match language {
+      "rust" | "rs" => vec![
+        HighlightRule::new("comment", r"//[^\n]*|/\*[\s\S]*?\*/"),
+        HighlightRule::new("string", r##"b?r#*"[\s\S]*?"#*|b?"(?:[^"\\]|\\[\s\S])*"|b?'(?:[^'\\\n]|\\.[^']*)'"##),
+        HighlightRule::new("lifetime", r"'[a-zA-Z_][a-zA-Z0-9_]*"),
+        HighlightRule::new("macro", r"[a-zA-Z_][a-zA-Z0-9_]*!"),
+        HighlightRule::new("keyword", r"\b(?:as|async|await|break|const|continue|crate|dyn|else|enum|extern|false|fn|for|if|impl|in|let|loop|match|mod|move|mut|pub|ref|return|self|Self|static|struct|super|trait|true|type|unsafe|use|where|while)\b"),
+        HighlightRule::new("type", r"\b(?:[A-Z][a-zA-Z0-9_]*|[iu](?:8|16|32|64|128|size)|f32|f64|bool|char|str)\b"),
+        HighlightRule::new("function", r"([a-z_][a-zA-Z0-9_]*)\s*(?:::<[^>]*>)?\("),
+        HighlightRule::new("number", r"\b0x[0-9a-fA-F_]+\b|\b[0-9][0-9_]*(?:\.[0-9]+)?(?:[iu](?:8|16|32|64|128|size)|f32|f64)?\b"),
+      ],
+      "html" | "xml" => vec![
+        HighlightRule::new("comment", r"<!--[\s\S]*?-->"),
+        HighlightRule::new("tag", r"</?[a-zA-Z!][a-zA-Z0-9-]*|/?>"),
+        HighlightRule::new("attribute", r"([a-zA-Z_:][a-zA-Z0-9_:.-]*)\s*="),
+        HighlightRule::new("string", r#""[^"]*"|'[^']*'"#),
+        HighlightRule::new("variable", r"&[a-zA-Z0-9#]+;"),
+      ],
+      "css" => vec![
+        HighlightRule::new("comment", r"/\*[\s\S]*?\*/"),
+        HighlightRule::new("string", string),
+        HighlightRule::new("keyword", r"@[a-zA-Z-]+|!important"),
+        HighlightRule::new("attribute", r"([a-zA-Z-]+)\s*:\s"),
+        HighlightRule::new("number", r"#[0-9a-fA-F]{3,8}\b|-?[0-9]*\.?[0-9]+(?:px|rem|em|%|vh|vw|s|ms|fr|deg)?"),
+        HighlightRule::new("type", r"[.#][a-zA-Z_-][a-zA-Z0-9_-]*|:{1,2}[a-zA-Z-]+"),
+        HighlightRule::new("function", r"([a-zA-Z-]+)\("),
+      ],
+      "bash" | "sh" | "shell" | "console" => vec![
+        HighlightRule::new("comment", r"#[^\n]*"),
+        HighlightRule::new("string", string),
+        HighlightRule::new("variable", r"\$\{[^}]*\}|\$[a-zA-Z0-9_?@#*]+"),
+        HighlightRule::new("keyword", r"\b(?:if|then|else|elif|fi|for|in|do|done|case|esac|while|until|function|export|local|return|sudo)\b"),
+        HighlightRule::new("attribute", r"--?[a-zA-Z][a-zA-Z0-9-]*"),
+      ],
+      "json" => vec![
+        HighlightRule::new("attribute", r#"("(?:[^"\\]|\\.)*")\s*:"#),
+        HighlightRule::new("string", r#""(?:[^"\\]|\\.)*""#),
+        HighlightRule::new("keyword", r"\b(?:true|false|null)\b"),
+        HighlightRule::new("number", number),
+      ],
+      "toml" => vec![
+        HighlightRule::new("comment", r"#[^\n]*"),
+        HighlightRule::at_line_start("type", r"\[\[?[^\]\n]+\]\]?"),
+        HighlightRule::new("attribute", r"([a-zA-Z0-9_.-]+)\s*="),
+        HighlightRule::new("string", r#""""[\s\S]*?"""|'''[\s\S]*?'''|"(?:[^"\\]|\\.)*"|'[^'\n]*'"#),
+        HighlightRule::new("keyword", r"\b(?:true|false)\b"),
+        HighlightRule::new("number", number),
+      ],
+      "http" => vec![
+        HighlightRule::new("keyword", r"\b(?:GET|POST|PUT|HEAD|DELETE|CONNECT|OPTIONS|TRACE|PATCH)\b"),
+        HighlightRule::new("type", r"HTTP/[0-9](?:\.[0-9])?"),
+        HighlightRule::at_line_start("attribute", r"([a-zA-Z][a-zA-Z0-9-]*):"),
+        HighlightRule::new("number", r"\b[1-5][0-9]{2}\b"),
+      ],
+      _ => return None,
+    };
+
+    // plain identifiers are consumed whole so keywords never match mid-word
+    rules.push(HighlightRule::new("", r"[a-zA-Z_][a-zA-Z0-9_]*"));
+    Some(rules)
+  }
+
+  fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+      .replace("&gt;", ">")
+      .replace("&quot;", "\"")
+      .replace("&#39;", "'")
+      .replace("&#x27;", "'")
+      .replace("&apos;", "'")
+      .replace("&amp;", "&")
+  }
+
+  fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+      .replace('<', "&lt;")
+      .replace('>', "&gt;")
+  }
+
+  fn tokenize<'a>(code: &'a str, rules: &[HighlightRule]) -> Vec<(&'static str, &'a str)> {
+    let mut tokens: Vec<(&'static str, &'a str)> = vec![];
+    let mut i = 0;
+
+    while i < code.len() {
+      let at_line_start = i == 0 || code[..i].ends_with('\n');
+      let token = rules.iter()
+        .filter(|rule| at_line_start || !rule.line_start)
+        .find_map(|rule| {
+          let captures = rule.regex.captures(&code[i..])?;
+          let token = captures.get(1).or(captures.get(0))?;
+          (!token.is_empty() && token.start() == 0).then_some((rule.class, token.as_str()))
+        });
+
+      let (class, text) = token.unwrap_or_else(|| {
+        let len = code[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        ("", &code[i..i + len])
+      });
+
+      // merge unhighlighted runs so the output doesn't get a span per character
+      match tokens.last_mut() {
+        Some((last_class, last_text)) if class.is_empty() && last_class.is_empty() => {
+          *last_text = &code[i - last_text.len()..i + text.len()];
+        }
+        _ => tokens.push((class, text)),
+      }
+      i += text.len();
+    }
+
+    tokens
+  }
+
+  fn parse_line_ranges(ranges: &str) -> Vec<(usize, usize)> {
+    ranges.split(',')
+      .filter_map(|range| {
+        let mut bounds = range.trim().splitn(2, '-').map(|b| b.trim().parse::<usize>());
+        let start = bounds.next()?.ok()?;
+        let end = bounds.next().and_then(|b| b.ok()).unwrap_or(start);
+        Some((start, end))
+      })
+      .collect()
+  }
+
+  fn format_token(class: &str, text: &str) -> String {
+    match class {
+      "" => Self::escape_html(text),
+      class => format!("<span class=\"hl-{}\">{}</span>", class, Self::escape_html(text)),
+    }
+  }
+
+  fn format_lines(tokens: &[(&str, &str)], line_numbers: bool, highlighted: &[(usize, usize)]) -> String {
+    // tokens spanning several lines (e.g. block comments) are split so every
+    // line can be wrapped in its own element
+    let mut lines: Vec<String> = vec![String::new()];
+    for (class, text) in tokens {
+      for (n, part) in text.split('\n').enumerate() {
+        if n > 0 {
+          lines.push(String::new());
+        }
+        if !part.is_empty() {
+          lines.last_mut().expect("There is always a line").push_str(&Self::format_token(class, part));
+        }
+      }
+    }
+    if lines.len() > 1 && lines.last().is_some_and(|line| line.is_empty()) {
+      lines.pop();
+    }
+
+    lines.iter()
+      .enumerate()
+      .map(|(i, line)| {
+        let number = i + 1;
+        let is_highlighted = highlighted.iter().any(|(start, end)| (*start..=*end).contains(&number));
+        format!(
+          "<span class=\"hl-line{}\"{}>{}</span>\n",
+          if is_highlighted { " hl-highlighted" } else { "" },
+          if line_numbers { format!(" data-line=\"{}\"", number) } else { String::new() },
+          line
+        )
+      })
+      .collect()
+  }
+
+  fn highlight_block(captures: &Captures) -> String {
+    let (pre_attributes, code_attributes, code) = (&captures[1], &captures[2], &captures[3]);
+    let Some(rules) = Self::language_regex()
+      .captures(code_attributes)
+      .and_then(|c| Self::rules(&c[1])) else {
+      return String::from(&captures[0]);
+    };
+
+    let code = Self::unescape_html(code);
+    let tokens = Self::tokenize(&code, &rules);
+    let line_numbers = pre_attributes.contains("data-line-numbers");
+    let highlighted = Self::highlight_lines_regex()
+      .captures(pre_attributes)
+      .map(|c| Self::parse_line_ranges(&c[1]))
+      .unwrap_or_default();
+
+    let body = if line_numbers || !highlighted.is_empty() {
+      Self::format_lines(&tokens, line_numbers, &highlighted)
+    } else {
+      tokens.iter().map(|(class, text)| Self::format_token(class, text)).collect()
+    };
+
+    format!("<pre{}><code{}>{}</code></pre>", pre_attributes, code_attributes, body)
+  }
+
+  pub fn has_code_blocks(html: &str) -> bool {
+    Self::code_block_regex()
+      .captures_iter(html)
+      .any(|c| Self::language_regex().is_match(&c[2]))
+  }
+
+  pub fn highlight(html: &str) -> String {
+    Self::code_block_regex()
+      .replace_all(html, |captures: &Captures| Self::highlight_block(captures))
+      .into_owned()
+  }
+
+  pub fn stylesheet() -> String {
+    let mut css: String = Self::THEME.iter()
+      .map(|(class, style)| format!(".hl-{} {{ {}; }}\n", class, style))
+      .collect();
+
+    css.push_str(".hl-line { display: inline-block; width: 100%; }\n");
+    css.push_str(".hl-highlighted { background-color: rgba(196, 30, 61, 0.1); }\n");
+    css.push_str("pre[data-line-numbers] .hl-line::before { content: attr(data-line); display: inline-block; width: 2.5em; margin-right: 1em; text-align: right; color: #6a737d; user-select: none; }\n");
+    css
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn highlights_tokens_and_keeps_code_escaped() {
+    let html = "<pre><code class=\"language-rust\">if a &lt; b &amp;&amp; c { x(\"&lt;/code&gt;\") }</code></pre>";
+    assert_eq!(
+      SiteHighlighter::highlight(html),
+      "<pre><code class=\"language-rust\"><span class=\"hl-keyword\">if</span> a &lt; b &amp;&amp; c { \
+       <span class=\"hl-function\">x</span>(<span class=\"hl-string\">\"&lt;/code&gt;\"</span>) }</code></pre>",
+    );
+  }
+
+  #[test]
+  fn keywords_do_not_match_inside_identifiers() {
+    let html = "<pre><code class=\"language-rust\">let format = iffy;</code></pre>";
+    assert_eq!(
+      SiteHighlighter::highlight(html),
+      "<pre><code class=\"language-rust\"><span class=\"hl-keyword\">let</span> format = iffy;</code></pre>",
+    );
+  }
+
+  #[test]
+  fn unknown_and_missing_languages_are_left_alone() {
+    let html = "<pre><code class=\"language-cobol\">a &lt; b</code></pre><pre><code>a &lt; b</code></pre>";
+    assert_eq!(SiteHighlighter::highlight(html), html);
+    assert!(!SiteHighlighter::has_code_blocks("<pre><code>a &lt; b</code></pre>"));
+    assert!(SiteHighlighter::has_code_blocks("<pre><code class=\"language-toml\">a = 1</code></pre>"));
+  }
+
+  #[test]
+  fn multi_line_tokens_are_split_per_line() {
+    let html = "<pre data-line-numbers data-highlight=\"2\"><code class=\"language-css\">/* a\nb */\np {}\n</code></pre>";
+    assert_eq!(
+      SiteHighlighter::highlight(html),
+      "<pre data-line-numbers data-highlight=\"2\"><code class=\"language-css\">\
+       <span class=\"hl-line\" data-line=\"1\"><span class=\"hl-comment\">/* a</span></span>\n\
+       <span class=\"hl-line hl-highlighted\" data-line=\"2\"><span class=\"hl-comment\">b */</span></span>\n\
+       <span class=\"hl-line\" data-line=\"3\">p {}</span>\n</code></pre>",
+    );
+  }
+
+  #[test]
+  fn line_ranges_skip_malformed_entries() {
+    assert_eq!(SiteHighlighter::parse_line_ranges("1, 3-5,x,7-"), vec![(1, 1), (3, 5), (7, 7)]);
+  }
+}
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, fs, io::{self, Cursor}, path::Path};
+
+use image::{DynamicImage, GenericImageView, ImageFormat, codecs::{avif::AvifEncoder, jpeg::JpegEncoder}, imageops::FilterType};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::site::{hash::SiteHash, route::SiteRoute, xml::SiteXml};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteImageOptions {
+  pub enabled: bool,
+  pub widths: Vec<u32>,
+  pub webp: bool,
+  pub avif: bool,
+}
+
+impl Default for SiteImageOptions {
+  fn default() -> Self {
+    SiteImageOptions {
+      enabled: true,
+      widths: vec![160, 320, 640, 1280],
+      webp: true,
+      avif: true,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SiteImageFormat {
+  Png,
+  Jpeg,
+  Webp,
+  Avif,
+}
+
+impl SiteImageFormat {
+  fn from_extension(extension: &str) -> Option<SiteImageFormat> {
+    match extension.to_lowercase().as_str() {
+      "png" => Some(SiteImageFormat::Png),
+      "jpg" | "jpeg" => Some(SiteImageFormat::Jpeg),
+      _ => None,
+    }
+  }
+
+  fn extension(&self) -> &'static str {
+    match self {
+      SiteImageFormat::Png => "png",
+      SiteImageFormat::Jpeg => "jpg",
+      SiteImageFormat::Webp => "webp",
+      SiteImageFormat::Avif => "avif",
+    }
+  }
+
+  fn content_type(&self) -> &'static str {
+    match self {
+      SiteImageFormat::Png => "image/png",
+      SiteImageFormat::Jpeg => "image/jpeg",
+      SiteImageFormat::Webp => "image/webp",
+      SiteImageFormat::Avif => "image/avif",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct SiteImageSource {
+  format: SiteImageFormat,
+  // resource and width of each variant, smallest first
+  candidates: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone)]
+struct SiteImage {
+  width: u32,
+  height: u32,
+  // the fallback in the original format comes last
+  sources: Vec<SiteImageSource>,
+}
+
+pub struct SiteImages;
+impl SiteImages {
+  const VARIANTS_DIR: &str = "images";
+  const AVIF_SPEED: u8 = 8;
+  const AVIF_QUALITY: u8 = 70;
+  const JPEG_QUALITY: u8 = 80;
+
+  fn img_regex() -> Regex {
+    Regex::new(r"<img\s[^>]*>").unwrap()
+  }
+
+  fn io_error(error: image::ImageError) -> io::Error {
+    io::Error::other(error)
+  }
+
+  fn encode(image: &DynamicImage, format: SiteImageFormat) -> io::Result<Vec<u8>> {
+    let mut bytes = Cursor::new(vec![]);
+    match format {
+      SiteImageFormat::Png => image.write_to(&mut bytes, ImageFormat::Png),
+      SiteImageFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut bytes, ImageFormat::WebP),
+      SiteImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, Self::JPEG_QUALITY)),
+      SiteImageFormat::Avif => DynamicImage::ImageRgba8(image.to_rgba8())
+        .write_with_encoder(AvifEncoder::new_with_speed_quality(&mut bytes, Self::AVIF_SPEED, Self::AVIF_QUALITY)),
+    }.map_err(Self::io_error)?;
+    Ok(bytes.into_inner())
+  }
+
+  // encoding is slow (AVIF especially), so variants are kept in the cache
+  // directory keyed by a hash of the source image
+  fn variant(image: &DynamicImage, source_hash: &str, width: u32, format: SiteImageFormat, cache_dir: &Path) -> io::Result<Vec<u8>> {
+    let cached = cache_dir.join(format!("{}-{}.{}", source_hash, width, format.extension()));
+    if let Ok(bytes) = fs::read(&cached) {
+      return Ok(bytes);
+    }
+
+    let resized = if width < image.width() {
+      image.resize(width, u32::MAX, FilterType::Lanczos3)
+    } else {
+      image.clone()
+    };
+    let bytes = Self::encode(&resized, format)?;
+    fs::create_dir_all(cache_dir)?;
+    fs::write(&cached, &bytes)?;
+    Ok(bytes)
+  }
+
+  fn process_image(dist: &Path, route: &SiteRoute, format: SiteImageFormat, routes: &mut Vec<SiteRoute>, options: &SiteImageOptions, cache_dir: &Path) -> io::Result<SiteImage> {
+    let bytes = fs::read(dist.join(&route.file))?;
+    let image = image::load_from_memory(&bytes).map_err(Self::io_error)?;
+    let (width, height) = image.dimensions();
+    let source_hash = SiteHash::short_hex(&bytes);
+    // variants mirror the path of the original, so images of the same name in
+    // different directories do not overwrite each other
+    let stem = Path::new(&route.file).with_extension("").to_string_lossy().to_string();
+
+    let mut widths: Vec<u32> = options.widths.iter().copied().filter(|&w| w < width).collect();
+    widths.sort_unstable();
+    widths.push(width);
+
+    let mut formats = vec![];
+    if options.avif {
+      formats.push(SiteImageFormat::Avif);
+    }
+    if options.webp {
+      formats.push(SiteImageFormat::Webp);
+    }
+    formats.push(format);
+
+    let mut sources = vec![];
+    for variant_format in formats {
+      let mut candidates = vec![];
+      for &variant_width in &widths {
+        // the original file already is the full size variant in its own format
+        if variant_format == format && variant_width == width {
+          candidates.push((route.resource.clone(), width));
+          continue;
+        }
+
+        let file = format!("{}/{}-{}.{}", Self::VARIANTS_DIR, stem, variant_width, variant_format.extension());
+        fs::create_dir_all(dist.join(&file).parent().expect("Variant is inside the variants directory"))?;
+        fs::write(dist.join(&file), Self::variant(&image, &source_hash, variant_width, variant_format, cache_dir)?)?;
+        let resource = format!("/{}", file);
+        routes.push(SiteRoute::new(&resource, &file, Some(variant_format.content_type())));
+        candidates.push((resource, variant_width));
+      }
+      sources.push(SiteImageSource { format: variant_format, candidates });
+    }
+
+    Ok(SiteImage { width, height, sources })
+  }
+
+  fn srcset(source: &SiteImageSource) -> String {
+    source.candidates.iter()
+      .map(|(resource, width)| format!("{} {}w", resource, width))
+      .collect::<Vec<String>>()
+      .join(", ")
+  }
+
+  fn picture(tag: &str, image: &SiteImage) -> String {
+    // without a hint from the author, assume the image is shown no larger than its intrinsic size
+    let authored_sizes = SiteXml::attribute(tag, "sizes");
+    let sizes = authored_sizes.clone()
+      .unwrap_or_else(|| format!("(max-width: {}px) 100vw, {}px", image.width, image.width));
+    let (fallback, alternatives) = image.sources.split_last().expect("Image has a fallback source");
+
+    let mut attributes = format!(" srcset=\"{}\"", Self::srcset(fallback));
+    if authored_sizes.is_none() {
+      attributes.push_str(&format!(" sizes=\"{}\"", sizes));
+    }
+    if SiteXml::attribute(tag, "width").is_none() && SiteXml::attribute(tag, "height").is_none() {
+      attributes.push_str(&format!(" width=\"{}\" height=\"{}\"", image.width, image.height));
+    }
+    let img = match tag.strip_suffix("/>") {
+      Some(start) => format!("{}{} />", start.trim_end(), attributes),
+      None => format!("{}{}>", tag.strip_suffix('>').unwrap_or(tag), attributes),
+    };
+
+    let sources: String = alternatives.iter()
+      .map(|source| format!("<source type=\"{}\" srcset=\"{}\" sizes=\"{}\">", source.format.content_type(), Self::srcset(source), sizes))
+      .collect();
+    format!("<picture>{}{}</picture>", sources, img)
+  }
+
+  fn is_inside_picture(html: &str, position: usize) -> bool {
+    let before = &html[..position];
+    before.rfind("<picture").is_some_and(|open| before.rfind("</picture>").is_none_or(|close| close < open))
+  }
+
+  // generates resized WebP/AVIF variants of every raster image a compiled page
+  // shows and rewrites its <img> tags into <picture> elements with srcsets
+  pub fn process(dist: &Path, routes: &mut Vec<SiteRoute>, options: &SiteImageOptions, cache_dir: &Path) -> io::Result<()> {
+    let mut images: HashMap<String, SiteImage> = HashMap::new();
+
+    for entry in fs::read_dir(dist)? {
+      let path = entry?.path();
+      if path.extension().and_then(|e| e.to_str()) != Some("html") {
+        continue;
+      }
+
+      let name = path.file_name().expect("Page does not have filename.").to_string_lossy().to_string();
+      let base = routes.iter()
+        .find(|route| route.file == name)
+        .map(|route| route.resource.clone())
+        .unwrap_or_else(|| String::from("/"));
+      let html = fs::read_to_string(&path)?;
+
+      let mut rewritten = String::with_capacity(html.len());
+      let mut last = 0;
+      for img in Self::img_regex().find_iter(&html) {
+        let tag = img.as_str();
+        // images the author already gave sources for are left alone
+        if SiteXml::attribute(tag, "srcset").is_some() || Self::is_inside_picture(&html, img.start()) {
+          continue;
+        }
+        let Some(resource) = SiteXml::attribute(tag, "src").and_then(|src| SiteRoute::resolve_reference(&base, &src)) else {
+          continue;
+        };
+        let Some(route) = routes.iter().find(|route| route.resource == resource).cloned() else {
+          continue;
+        };
+        let Some(format) = Path::new(&route.file).extension().and_then(|e| SiteImageFormat::from_extension(&e.to_string_lossy())) else {
+          continue;
+        };
+
+        let image = match images.get(&resource) {
+          Some(image) => image.clone(),
+          None => {
+            let image = Self::process_image(dist, &route, format, routes, options, cache_dir)?;
+            images.insert(resource, image.clone());
+            image
+          }
+        };
+
+        rewritten.push_str(&html[last..img.start()]);
+        rewritten.push_str(&Self::picture(tag, &image));
+        last = img.end();
+      }
+      rewritten.push_str(&html[last..]);
+      fs::write(&path, rewritten)?;
+    }
+
+    Ok(())
+  }
+}
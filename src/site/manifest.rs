@@ -0,0 +1,37 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+// maps each compiled output to the hash of the inputs it was compiled from,
+// stored one "output hash" pair per line like the route table
+#[derive(Debug, Default)]
+pub struct SiteManifest {
+  entries: BTreeMap<String, String>,
+}
+
+impl SiteManifest {
+  pub fn read(path: impl AsRef<Path>) -> SiteManifest {
+    let entries = fs::read_to_string(path)
+      .unwrap_or_default()
+      .lines()
+      .filter_map(|line| line.split_once(' '))
+      .map(|(output, hash)| (String::from(output), String::from(hash.trim())))
+      .collect();
+    SiteManifest { entries }
+  }
+
+  pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(path, self.entries.iter()
+      .map(|(output, hash)| format!("{} {}\n", output, hash))
+      .collect::<String>())
+  }
+
+  pub fn is_current(&self, output: &str, hash: &str) -> bool {
+    self.entries.get(output).is_some_and(|recorded| recorded == hash)
+  }
+
+  pub fn insert(&mut self, output: &str, hash: &str) {
+    self.entries.insert(String::from(output), String::from(hash));
+  }
+}
@@ -0,0 +1,295 @@
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteMinifierOptions {
+  pub strip_comments: bool,
+  pub strip_attribute_quotes: bool,
+}
+
+impl Default for SiteMinifierOptions {
+  fn default() -> Self {
+    SiteMinifierOptions {
+      strip_comments: true,
+      strip_attribute_quotes: false,
+    }
+  }
+}
+
+#[derive(Debug)]
+enum HtmlToken<'a> {
+  Text(&'a str),
+  Comment(&'a str),
+  Tag { raw: &'a str, name: String, closing: bool },
+  // contents of script, style and textarea, which are never touched
+  RawText(&'a str),
+}
+
+pub struct SiteMinifier;
+impl SiteMinifier {
+  const RAW_TEXT_ELEMENTS: [&str; 3] = ["script", "style", "textarea"];
+  const PREFORMATTED_ELEMENTS: [&str; 1] = ["pre"];
+  const BLOCK_ELEMENTS: [&str; 45] = [
+    "!doctype", "html", "head", "body", "meta", "link", "title", "base", "script", "style", "noscript",
+    "div", "p", "ul", "ol", "li", "dl", "dt", "dd", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "footer", "nav", "main", "section", "article", "aside", "hr", "br", "pre",
+    "table", "thead", "tbody", "tfoot", "tr", "td", "th", "blockquote", "figure", "figcaption",
+  ];
+
+  fn find_tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, c) in html[start..].char_indices() {
+      match (quote, c) {
+        (Some(q), c) if c == q => quote = None,
+        (Some(_), _) => (),
+        (None, '"' | '\'') => quote = Some(c),
+        (None, '>') => return Some(start + i + 1),
+        _ => (),
+      }
+    }
+    None
+  }
+
+  fn tag_name(raw: &str) -> (String, bool) {
+    let inner = raw.trim_start_matches('<');
+    let closing = inner.starts_with('/');
+    let name = inner.trim_start_matches('/')
+      .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+      .next()
+      .unwrap_or_default()
+      .to_lowercase();
+    (name, closing)
+  }
+
+  fn is_tag_start(html: &str, i: usize) -> bool {
+    let mut chars = html[i..].chars();
+    chars.next() == Some('<') && chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!')
+  }
+
+  // where the `</name` that ends a raw text element starts, matched without
+  // regard to case on the bytes themselves so offsets stay valid in `html`
+  fn find_close_tag(html: &str, start: usize, name: &str) -> Option<usize> {
+    let close_tag = format!("</{}", name);
+    html.as_bytes()[start..]
+      .windows(close_tag.len())
+      .position(|window| window.eq_ignore_ascii_case(close_tag.as_bytes()))
+      .map(|e| start + e)
+  }
+
+  fn tokenize(html: &str) -> Vec<HtmlToken<'_>> {
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < html.len() {
+      if html[i..].starts_with("<!--") {
+        let end = html[i..].find("-->").map(|e| i + e + 3).unwrap_or(html.len());
+        tokens.push(HtmlToken::Comment(&html[i..end]));
+        i = end;
+      } else if Self::is_tag_start(html, i) {
+        let Some(end) = Self::find_tag_end(html, i) else {
+          tokens.push(HtmlToken::Text(&html[i..]));
+          break;
+        };
+        let raw = &html[i..end];
+        let (name, closing) = Self::tag_name(raw);
+        tokens.push(HtmlToken::Tag { raw, name: name.clone(), closing });
+        i = end;
+
+        if !closing && Self::RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+          let raw_end = Self::find_close_tag(html, i, &name).unwrap_or(html.len());
+          tokens.push(HtmlToken::RawText(&html[i..raw_end]));
+          i = raw_end;
+        }
+      } else {
+        let end = (i + 1..html.len())
+          .find(|&j| html.is_char_boundary(j) && Self::is_tag_start(html, j))
+          .unwrap_or(html.len());
+        tokens.push(HtmlToken::Text(&html[i..end]));
+        i = end;
+      }
+    }
+
+    tokens
+  }
+
+  fn can_unquote(value: &str) -> bool {
+    !value.is_empty()
+      && !value.ends_with('/')
+      && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:/#?&;%,+".contains(c))
+  }
+
+  fn minify_tag(raw: &str, options: &SiteMinifierOptions) -> String {
+    let inner = raw.trim_start_matches('<').trim_end_matches('>');
+    if inner.starts_with('!') || inner.starts_with('/') {
+      return format!("<{}>", inner.split_whitespace().collect::<Vec<&str>>().join(" "));
+    }
+
+    let self_closing = inner.trim_end().ends_with('/');
+    let inner = inner.trim_end().trim_end_matches('/');
+    let mut parts: Vec<String> = vec![];
+    let mut chars = inner.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+      if c.is_whitespace() {
+        chars.next();
+        continue;
+      }
+
+      let mut end = start;
+      while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() || c == '=' {
+          break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+      }
+      let name = &inner[start..end];
+
+      while chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+        chars.next();
+      }
+      if chars.peek().map(|&(_, c)| c) != Some('=') {
+        parts.push(String::from(name));
+        continue;
+      }
+      chars.next();
+      while chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+        chars.next();
+      }
+
+      let value = match chars.peek() {
+        Some(&(value_start, quote @ ('"' | '\''))) => {
+          chars.next();
+          let mut value_end = inner.len();
+          for (i, c) in chars.by_ref() {
+            if c == quote {
+              value_end = i;
+              break;
+            }
+          }
+          (&inner[value_start + 1..value_end], Some(quote))
+        }
+        Some(&(value_start, _)) => {
+          let mut value_end = inner.len();
+          while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() {
+              value_end = i;
+              break;
+            }
+            chars.next();
+          }
+          (&inner[value_start..value_end], None)
+        }
+        None => ("", Some('"')),
+      };
+
+      parts.push(match value {
+        (value, _) if options.strip_attribute_quotes && !self_closing && Self::can_unquote(value) => format!("{}={}", name, value),
+        (value, Some(quote)) => format!("{}={}{}{}", name, quote, value, quote),
+        (value, None) => format!("{}=\"{}\"", name, value),
+      });
+    }
+
+    format!("<{}{}>", parts.join(" "), if self_closing { " /" } else { "" })
+  }
+
+  fn is_block_boundary(token: Option<&HtmlToken>) -> bool {
+    match token {
+      None => true,
+      Some(HtmlToken::Tag { name, .. }) => Self::BLOCK_ELEMENTS.contains(&name.as_str()),
+      Some(HtmlToken::Comment(_)) => true,
+      _ => false,
+    }
+  }
+
+  fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+      if c.is_whitespace() {
+        if !in_whitespace {
+          collapsed.push(' ');
+        }
+        in_whitespace = true;
+      } else {
+        collapsed.push(c);
+        in_whitespace = false;
+      }
+    }
+    collapsed
+  }
+
+  pub fn minify(html: &str, options: &SiteMinifierOptions) -> String {
+    let tokens: Vec<HtmlToken> = Self::tokenize(html)
+      .into_iter()
+      .filter(|token| !(options.strip_comments && matches!(token, HtmlToken::Comment(_))))
+      .collect();
+
+    let mut minified = String::with_capacity(html.len());
+    let mut preformatted_depth: usize = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+      match token {
+        HtmlToken::Text(text) if preformatted_depth > 0 => minified.push_str(text),
+        HtmlToken::Text(text) => {
+          let mut text = Self::collapse_whitespace(text);
+          if Self::is_block_boundary(if i == 0 { None } else { tokens.get(i - 1) }) || minified.ends_with(' ') {
+            text = String::from(text.trim_start());
+          }
+          if Self::is_block_boundary(tokens.get(i + 1)) {
+            text = String::from(text.trim_end());
+          }
+          minified.push_str(&text);
+        }
+        HtmlToken::Comment(comment) => minified.push_str(comment),
+        HtmlToken::RawText(text) => minified.push_str(text),
+        HtmlToken::Tag { raw, name, closing } => {
+          if Self::PREFORMATTED_ELEMENTS.contains(&name.as_str()) {
+            if *closing {
+              preformatted_depth = preformatted_depth.saturating_sub(1);
+            } else {
+              preformatted_depth += 1;
+            }
+          }
+          minified.push_str(&Self::minify_tag(raw, options));
+        }
+      }
+    }
+
+    minified
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{SiteMinifier, SiteMinifierOptions};
+
+  fn minify(html: &str) -> String {
+    SiteMinifier::minify(html, &SiteMinifierOptions::default())
+  }
+
+  #[test]
+  fn whitespace_in_pre_and_textarea_survives() {
+    let html = "<div>\n  <pre>  fn main() {\n    x\n  }</pre>\n  <textarea>\n  a  b\n</textarea>\n</div>";
+    assert_eq!(minify(html), "<div><pre>  fn main() {\n    x\n  }</pre><textarea>\n  a  b\n</textarea></div>");
+  }
+
+  #[test]
+  fn comments_and_whitespace_between_tags_are_removed() {
+    let html = "<ul>\n  <!-- items -->\n  <li>one   two</li>\n  <li>three</li>\n</ul>";
+    assert_eq!(minify(html), "<ul><li>one two</li><li>three</li></ul>");
+  }
+
+  #[test]
+  fn raw_text_ends_at_a_close_tag_in_any_case_after_non_ascii_text() {
+    let html = "<p>İstanbul</p><script>let s = \"İ  İ\";</SCRIPT>\n<p>after</p>";
+    assert_eq!(minify(html), "<p>İstanbul</p><script>let s = \"İ  İ\";</SCRIPT><p>after</p>");
+  }
+
+  #[test]
+  fn attribute_quotes_are_only_stripped_when_asked() {
+    let options = SiteMinifierOptions { strip_attribute_quotes: true, ..SiteMinifierOptions::default() };
+    assert_eq!(SiteMinifier::minify("<a  href=\"/blog\"  title=\"a b\">x</a>", &options), "<a href=/blog title=\"a b\">x</a>");
+    assert_eq!(minify("<a href=\"/blog\">x</a>"), "<a href=\"/blog\">x</a>");
+  }
+}
@@ -1,6 +1,28 @@
 pub mod builder;
 pub mod component;
+pub mod config;
+pub mod csp;
+pub mod css;
+pub mod data;
+pub mod feed;
+pub mod fingerprint;
 pub mod fs;
+pub mod graph;
+pub mod hash;
 pub mod header;
+pub mod headings;
+pub mod highlighter;
+pub mod images;
+pub mod manifest;
+pub mod minifier;
+pub mod options;
 pub mod parser;
+pub mod post;
+pub mod robots;
+pub mod route;
+pub mod scaffold;
+pub mod sitemap;
+pub mod taxonomy;
 pub mod template;
+pub mod watcher;
+pub mod xml;
@@ -0,0 +1,53 @@
+use std::{env, path::PathBuf, process};
+
+use crate::site::config::SiteConfig;
+
+#[derive(Debug, Clone)]
+pub struct SiteBuildOptions {
+  pub site: SiteConfig,
+  pub include_drafts: bool,
+  pub fingerprint_assets: bool,
+  // reuse pages compiled by the previous build whose inputs are unchanged;
+  // the stages after page compilation always run
+  pub incremental: bool,
+  // build in a temporary directory that is removed afterwards, to report
+  // problems without touching the output or the manifest
+  pub check: bool,
+}
+
+impl Default for SiteBuildOptions {
+  fn default() -> Self {
+    SiteBuildOptions {
+      site: SiteConfig::default(),
+      include_drafts: false,
+      fingerprint_assets: true,
+      incremental: true,
+      check: false,
+    }
+  }
+}
+
+impl SiteBuildOptions {
+  // where the site is put together before it is synced into the output; a
+  // check stages apart from builds, so one can run next to `serve --watch`
+  pub fn staging(&self) -> PathBuf {
+    match self.check {
+      true => env::temp_dir().join(format!("site-check-{}", process::id())),
+      false => self.site.directories.build(),
+    }
+  }
+
+  // what a compiled page depends on besides its own inputs, so that changing
+  // the server settings or the asset pipeline does not recompile every page
+  pub fn page_salt(&self) -> String {
+    let site = &self.site;
+    format!(
+      "{}\n{}\n{}\n{}\n{:?}",
+      self.include_drafts,
+      site.headers().iter().map(|header| header.value.as_str()).collect::<Vec<&str>>().join("\n"),
+      site.build.date_format,
+      site.build.heading_anchors,
+      site.build.minify,
+    )
+  }
+}
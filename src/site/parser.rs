@@ -1,8 +1,10 @@
-use std::{io, rc::Rc};
+use std::{io, path::Path, rc::Rc};
 
+use chrono::{Datelike, NaiveDate};
 use regex::Regex;
+use serde_json::Value;
 
-use crate::site::{component::SiteComponent, header::SiteHeader, template::SiteTemplate};
+use crate::site::{component::SiteComponent, data::SiteData, header::SiteHeader, post::SitePost, taxonomy::SiteTaxonomy, template::SiteTemplate};
 
 pub struct SiteParser;
 impl SiteParser {
@@ -14,8 +16,24 @@ impl SiteParser {
     Regex::new(r"\[\[([a-z0-9_]+)\]\]((?:[[:space:]]\[[a-z0-9_]+=[^\[\]]+\])+)").unwrap()
   }
 
-  fn parse_headers_from_string(content: &str) -> Rc<[SiteHeader]> {
-    SiteParser::header_regex().captures_iter(&content)
+  fn post_date_regex() -> Regex {
+    Regex::new(r"^([0-9]{4}-[0-9]{2}-[0-9]{2})-").unwrap()
+  }
+
+  fn ordinal_regex() -> Regex {
+    Regex::new(r"([0-9]+)(st|nd|rd|th)\b").unwrap()
+  }
+
+  fn paragraph_regex() -> Regex {
+    Regex::new(r"(?s)<p>(.*?)</p>").unwrap()
+  }
+
+  fn tag_regex() -> Regex {
+    Regex::new(r"<[^>]+>").unwrap()
+  }
+
+  pub fn parse_headers_from_string(content: &str) -> Rc<[SiteHeader]> {
+    SiteParser::header_regex().captures_iter(content)
       .map(|c| c.extract())
       .map(|(_, [header_name, header_value])| SiteHeader {
         name: String::from(header_name),
@@ -28,7 +46,7 @@ impl SiteParser {
     let mut component_body = components
       .iter()
       .find(|c| c.name == component_name)
-      .expect(&format!("No component with name \"{}\" found", component_name))
+      .unwrap_or_else(|| panic!("No component with name \"{}\" found", component_name))
       .body
       .clone();
 
@@ -42,31 +60,138 @@ impl SiteParser {
     component_body
   }
 
-  fn parse_body_from_string(content: &str, components: Rc<[SiteComponent]>) -> String {
+  fn generate_component_loop(
+    components: Rc<[SiteComponent]>,
+    data: Rc<[SiteData]>,
+    context: Option<&Value>,
+    component_name: &str,
+    headers: Rc<[SiteHeader]>,
+    data_path: &str
+  ) -> String {
+    SiteData::resolve(&data, context, data_path)
+      .and_then(|items| items.as_array())
+      .unwrap_or_else(|| panic!("No data list \"{}\" found", data_path))
+      .iter()
+      .map(|item| {
+        let item_headers: Rc<[SiteHeader]> = SiteData::to_headers(item)
+          .into_iter()
+          .chain(headers.iter().filter(|h| h.name != "each" && h.name != "separator").cloned())
+          .collect();
+
+        SiteParser::parse_body_from_string(
+          &SiteParser::generate_component(components.clone(), component_name, item_headers),
+          components.clone(),
+          data.clone(),
+          Some(item)
+        )
+      })
+      .collect::<Vec<String>>()
+      .join(&SiteHeader::get_header(&headers, "separator").unwrap_or_default())
+  }
+
+  fn parse_body_from_string(content: &str, components: Rc<[SiteComponent]>, data: Rc<[SiteData]>, context: Option<&Value>) -> String {
     let mut body = String::from(content);
 
     while let Some(captures) = SiteParser::component_regex().captures(&body) {
       let component_name = captures.get(1).expect("No component name found").as_str();
       let component_headers = SiteParser::parse_headers_from_string(captures.get(2).expect("No component headers found").as_str());
 
-      body.replace_range(
-        captures.get_match().range(),
-        &SiteParser::generate_component(components.clone(), component_name, component_headers)
-      );
+      let component_body = match SiteHeader::get_header(&component_headers, "each") {
+        Some(data_path) => SiteParser::generate_component_loop(
+          components.clone(),
+          data.clone(),
+          context,
+          component_name,
+          component_headers,
+          &data_path
+        ),
+        None => SiteParser::generate_component(components.clone(), component_name, component_headers),
+      };
+
+      body.replace_range(captures.get_match().range(), &component_body);
     }
 
     body
   }
 
-  pub fn parse_template_from_string(content: &str, name: &str, components: Rc<[SiteComponent]>) -> io::Result<SiteTemplate> {
+  pub fn parse_template_from_string(content: &str, path: &Path, components: Rc<[SiteComponent]>, data: Rc<[SiteData]>) -> io::Result<SiteTemplate> {
     let mut file_contents = content.split("---");
     let file_headers = file_contents.next().expect("File does not have file headers");
     let file_body = file_contents.next().expect("File does not have contents.");
 
-    return Ok(SiteTemplate {
-      name: String::from(name),
-      headers: SiteParser::parse_headers_from_string(&file_headers),
-      body: SiteParser::parse_body_from_string(&file_body, components),
-    });
+    Ok(SiteTemplate {
+      name: path.file_name().expect("Page does not have filename.").to_string_lossy().to_string(),
+      path: path.to_path_buf(),
+      headers: SiteParser::parse_headers_from_string(file_headers),
+      body: SiteParser::parse_body_from_string(file_body, components, data, None),
+    })
+  }
+
+  fn summarize_body(body: &str) -> String {
+    SiteParser::paragraph_regex()
+      .captures(body)
+      .map(|c| SiteParser::tag_regex().replace_all(&c[1], "").into_owned())
+      .unwrap_or_default()
+      .split_whitespace()
+      .collect::<Vec<&str>>()
+      .join(" ")
+  }
+
+  // dates are read in the configured format, which is also how they are
+  // written, or in one of a few common ones
+  pub fn parse_date(value: &str, date_format: &str) -> Option<NaiveDate> {
+    let value = SiteParser::ordinal_regex().replace_all(value.trim(), "$1");
+    let configured = date_format.replace("%o", "%d");
+    [configured.as_str(), "%Y-%m-%d", "%B %d, %Y", "%b %d, %Y", "%d %B %Y", "%d %b %Y"]
+      .iter()
+      .find_map(|format| NaiveDate::parse_from_str(&value, format).ok())
+  }
+
+  pub fn format_date(date: NaiveDate, format: &str) -> String {
+    let ordinal = match date.day() {
+      11..=13 => "th",
+      day if day % 10 == 1 => "st",
+      day if day % 10 == 2 => "nd",
+      day if day % 10 == 3 => "rd",
+      _ => "th",
+    };
+    date.format(&format.replace("%o", &format!("{}{}", date.day(), ordinal))).to_string()
+  }
+
+  pub fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+      .map(|item| item.trim())
+      .filter(|item| !item.is_empty())
+      .map(String::from)
+      .collect()
+  }
+
+  pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+      .split(|c: char| !c.is_alphanumeric())
+      .filter(|word| !word.is_empty())
+      .collect::<Vec<&str>>()
+      .join("-")
+  }
+
+  pub fn parse_post_from_template(template: &SiteTemplate, url: &str, date_format: &str) -> Option<SitePost> {
+    let published = SiteHeader::get_header(&template.headers, "published")
+      .and_then(|published| SiteParser::parse_date(&published, date_format))
+      .or_else(|| SiteParser::post_date_regex()
+        .captures(&template.name)
+        .and_then(|c| NaiveDate::parse_from_str(&c[1], "%Y-%m-%d").ok()))?;
+
+    Some(SitePost {
+      title: SiteHeader::get_header(&template.headers, "article_title")?,
+      url: String::from(url),
+      published,
+      updated: SiteHeader::get_header(&template.headers, "updated")
+        .and_then(|updated| SiteParser::parse_date(&updated, date_format))
+        .unwrap_or(published),
+      summary: SiteHeader::get_header(&template.headers, "summary")
+        .unwrap_or_else(|| SiteParser::summarize_body(&template.body)),
+      tags: SiteTaxonomy::parse_tags(&template.headers),
+      content: String::from(template.body.trim()),
+    })
   }
 }
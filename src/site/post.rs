@@ -0,0 +1,21 @@
+use chrono::NaiveDate;
+
+use crate::site::{parser::SiteParser, xml::SiteXml};
+
+#[derive(Debug, Clone)]
+pub struct SitePost {
+  pub title: String,
+  pub url: String,
+  pub published: NaiveDate,
+  pub updated: NaiveDate,
+  pub summary: String,
+  pub tags: Vec<String>,
+  pub content: String,
+}
+
+impl SitePost {
+  pub fn list_item(&self, date_format: &str) -> String {
+    let published = SiteParser::format_date(self.published, date_format);
+    format!("<li>({}) <a href=\"{}\">{}</a></li>", SiteXml::escape(&published), SiteXml::escape(&self.url), SiteXml::escape(&self.title))
+  }
+}
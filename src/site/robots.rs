@@ -0,0 +1,42 @@
+use crate::site::header::SiteHeader;
+
+pub struct SiteRobots;
+impl SiteRobots {
+  pub const FILE: &str = "robots.txt";
+  pub const CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+  fn directive_name(header_name: &str) -> Option<&'static str> {
+    match header_name {
+      "user_agent" => Some("User-agent"),
+      "allow" => Some("Allow"),
+      "disallow" => Some("Disallow"),
+      "crawl_delay" => Some("Crawl-delay"),
+      _ => None,
+    }
+  }
+
+  pub fn generate(config: &[SiteHeader], sitemap_url: &str) -> String {
+    let mut lines: Vec<String> = vec![];
+    for header in config {
+      let Some(directive) = Self::directive_name(&header.name) else {
+        continue;
+      };
+
+      // each user agent starts a new group
+      if directive == "User-agent" && !lines.is_empty() {
+        lines.push(String::new());
+      }
+      lines.push(format!("{}: {}", directive, header.value.trim()));
+    }
+
+    if lines.is_empty() {
+      lines.push(String::from("User-agent: *"));
+      lines.push(String::from("Allow: /"));
+    }
+
+    lines.push(String::new());
+    lines.push(format!("Sitemap: {}", sitemap_url));
+    lines.push(String::new());
+    lines.join("\n")
+  }
+}
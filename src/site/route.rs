@@ -0,0 +1,62 @@
+#[derive(Debug, Clone)]
+pub struct SiteRoute {
+  pub resource: String,
+  pub file: String,
+  pub content_type: Option<String>,
+}
+
+impl SiteRoute {
+  pub fn new(resource: &str, file: &str, content_type: Option<&str>) -> SiteRoute {
+    SiteRoute {
+      resource: String::from(resource),
+      file: String::from(file),
+      content_type: content_type.map(String::from),
+    }
+  }
+
+  pub fn parse(line: &str) -> Option<SiteRoute> {
+    let line = line.trim();
+    if line.starts_with('#') || line.is_empty() {
+      return None;
+    }
+
+    // the content type may itself contain spaces, e.g. "text/plain; charset=utf-8"
+    let mut parts = line.splitn(3, char::is_whitespace).map(str::trim);
+    Some(SiteRoute::new(parts.next()?, parts.next()?, parts.next().filter(|c| !c.is_empty())))
+  }
+
+  pub fn format(&self) -> String {
+    match &self.content_type {
+      Some(content_type) => format!("{} {} {}", self.resource, self.file, content_type),
+      None => format!("{} {}", self.resource, self.file),
+    }
+  }
+
+  // resolves a reference found in the document served at `base` to an
+  // absolute resource, ignoring external, data and fragment-only references
+  pub fn resolve_reference(base: &str, reference: &str) -> Option<String> {
+    let reference = reference.trim();
+    if reference.is_empty()
+      || reference.starts_with('#')
+      || reference.starts_with("//")
+      || reference.contains(':') {
+      return None;
+    }
+
+    let path = reference.split(['?', '#']).next()?;
+    let joined = match path.starts_with('/') {
+      true => String::from(path),
+      false => format!("{}/{}", base.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default(), path),
+    };
+
+    let mut segments: Vec<&str> = vec![];
+    for segment in joined.split('/') {
+      match segment {
+        "" | "." => (),
+        ".." => { segments.pop(); }
+        segment => segments.push(segment),
+      }
+    }
+    Some(format!("/{}", segments.join("/")))
+  }
+}
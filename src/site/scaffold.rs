@@ -0,0 +1,57 @@
+use std::{fs, io, path::PathBuf};
+
+use chrono::NaiveDate;
+
+use crate::site::{config::SiteDirectories, parser::SiteParser};
+
+pub struct SiteScaffold;
+impl SiteScaffold {
+  const POST_TEMPLATE: &str = "article.html";
+
+  fn post_body(title: &str, published: NaiveDate, date_format: &str) -> String {
+    format!(
+      "[template={}]\n[article_title={}]\n[published={}]\n[draft=true]\n---\n<section>\n  <h2>{}</h2>\n  <p>\n  </p>\n</section>\n",
+      Self::POST_TEMPLATE,
+      title,
+      SiteParser::format_date(published, date_format),
+      title,
+    )
+  }
+
+  // routes for posts are kept together, so the new one goes after the last
+  fn add_route(routes: &str, resource: &str, file: &str) -> String {
+    let mut lines: Vec<String> = routes.lines().map(String::from).collect();
+    let position = lines.iter()
+      .rposition(|line| line.starts_with("/blog/"))
+      .map(|i| i + 1)
+      .unwrap_or(lines.len());
+    lines.insert(position, format!("{} {}", resource, file));
+    format!("{}\n", lines.join("\n"))
+  }
+
+  // writes a draft post named after its date and title and routes it under
+  // /blog, returning the new file; the date in the file name is always
+  // year-month-day, and the one in the post uses the configured format
+  pub fn new_post(title: &str, published: NaiveDate, directories: &SiteDirectories, date_format: &str) -> io::Result<PathBuf> {
+    let title = title.trim();
+    if title.is_empty() || title.contains(['[', ']']) {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "Post titles must be non-empty and cannot contain brackets"));
+    }
+
+    let slug = SiteParser::slugify(title);
+    if slug.is_empty() {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "Post titles need at least one letter or digit to name the post after"));
+    }
+    let file = format!("{}-{}.html", published.format("%Y-%m-%d"), slug);
+    let path = directories.posts().join(&file);
+    if path.exists() {
+      return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", path.display())));
+    }
+
+    fs::create_dir_all(directories.posts())?;
+    fs::write(&path, Self::post_body(title, published, date_format))?;
+    let routes = fs::read_to_string(directories.routes_file())?;
+    fs::write(directories.routes_file(), Self::add_route(&routes, &format!("/blog/{}", slug), &file))?;
+    Ok(path)
+  }
+}
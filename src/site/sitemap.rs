@@ -0,0 +1,37 @@
+use chrono::NaiveDate;
+
+use crate::site::xml::SiteXml;
+
+#[derive(Debug, Clone)]
+pub struct SiteSitemapEntry {
+  pub url: String,
+  pub lastmod: NaiveDate,
+  pub changefreq: Option<String>,
+  pub priority: Option<String>,
+}
+
+pub struct SiteSitemap;
+impl SiteSitemap {
+  pub const FILE: &str = "sitemap.xml";
+  pub const CONTENT_TYPE: &str = "application/xml";
+
+  fn format_entry(entry: &SiteSitemapEntry) -> String {
+    [
+      String::from("<url>"),
+      SiteXml::element("loc", &entry.url),
+      SiteXml::element("lastmod", &entry.lastmod.format("%Y-%m-%d").to_string()),
+      entry.changefreq.as_deref().map(|c| SiteXml::element("changefreq", c)).unwrap_or_default(),
+      entry.priority.as_deref().map(|p| SiteXml::element("priority", p)).unwrap_or_default(),
+      String::from("</url>"),
+    ].concat()
+  }
+
+  pub fn generate(entries: &[SiteSitemapEntry]) -> String {
+    [
+      String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n"),
+      String::from("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">"),
+      entries.iter().map(Self::format_entry).collect(),
+      String::from("</urlset>\n"),
+    ].concat()
+  }
+}
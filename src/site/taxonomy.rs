@@ -0,0 +1,91 @@
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::NaiveDate;
+
+use crate::site::{header::SiteHeader, parser::SiteParser, post::SitePost, route::SiteRoute, template::SiteTemplate, xml::SiteXml};
+
+pub struct SiteTaxonomy;
+impl SiteTaxonomy {
+  const TAGS_RESOURCE: &str = "/blog/tags";
+  const TAGS_FILE: &str = "tags.html";
+  const TAGS_TEMPLATE: &str = "tags.html";
+  const TAG_TEMPLATE: &str = "tag.html";
+
+  // tags without a letter or digit have no slug to link to, so they are skipped
+  pub fn parse_tags(headers: &[SiteHeader]) -> Vec<String> {
+    SiteHeader::get_header(headers, "tags")
+      .map(|tags| SiteParser::parse_list(&tags))
+      .unwrap_or_default()
+      .into_iter()
+      .filter(|tag| !SiteParser::slugify(tag).is_empty())
+      .collect()
+  }
+
+  pub fn tag_url(tag: &str) -> String {
+    format!("{}/{}", Self::TAGS_RESOURCE, SiteParser::slugify(tag))
+  }
+
+  pub fn tag_links(tags: &[String]) -> String {
+    tags.iter()
+      .map(|tag| format!("<a href=\"{}\">{}</a>", Self::tag_url(tag), SiteXml::escape(tag)))
+      .collect::<Vec<String>>()
+      .join(", ")
+  }
+
+  fn generated_page(templates: &Path, name: &str, template: &str, headers: Vec<SiteHeader>, body: String) -> SiteTemplate {
+    SiteTemplate {
+      name: String::from(name),
+      path: templates.join(template),
+      headers: [vec![SiteHeader { name: String::from("template"), value: String::from(template) }], headers]
+        .concat()
+        .into(),
+      body,
+    }
+  }
+
+  fn lastmod_header(posts: &[&SitePost]) -> Vec<SiteHeader> {
+    posts.iter()
+      .map(|p| p.updated)
+      .max()
+      .map(|lastmod: NaiveDate| vec![SiteHeader { name: String::from("lastmod"), value: lastmod.format("%Y-%m-%d").to_string() }])
+      .unwrap_or_default()
+  }
+
+  pub fn generate_pages(posts: &[SitePost], templates: &Path, date_format: &str) -> (Vec<SiteRoute>, Vec<SiteTemplate>) {
+    // keyed on slug so that "Rust" and "rust" share a page, displayed as first seen
+    let mut tags: BTreeMap<String, (String, Vec<&SitePost>)> = BTreeMap::new();
+    for post in posts {
+      for tag in &post.tags {
+        tags.entry(SiteParser::slugify(tag))
+          .or_insert_with(|| (tag.clone(), vec![]))
+          .1
+          .push(post);
+      }
+    }
+
+    let mut routes = vec![SiteRoute::new(Self::TAGS_RESOURCE, Self::TAGS_FILE, None)];
+    let mut pages = vec![Self::generated_page(
+      templates,
+      Self::TAGS_FILE,
+      Self::TAGS_TEMPLATE,
+      Self::lastmod_header(&posts.iter().collect::<Vec<&SitePost>>()),
+      tags.values()
+        .map(|(tag, tagged)| format!("<li><a href=\"{}\">{}</a> ({})</li>", Self::tag_url(tag), SiteXml::escape(tag), tagged.len()))
+        .collect(),
+    )];
+
+    for (slug, (tag, tagged)) in &tags {
+      let file = format!("tag-{}.html", slug);
+      routes.push(SiteRoute::new(&Self::tag_url(tag), &file, None));
+      pages.push(Self::generated_page(
+        templates,
+        &file,
+        Self::TAG_TEMPLATE,
+        [vec![SiteHeader { name: String::from("tag"), value: SiteXml::escape(tag) }], Self::lastmod_header(tagged)].concat(),
+        tagged.iter().map(|post| post.list_item(date_format)).collect(),
+      ));
+    }
+
+    (routes, pages)
+  }
+}
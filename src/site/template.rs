@@ -1,10 +1,11 @@
-use std::rc::Rc;
+use std::{path::PathBuf, rc::Rc};
 
 use crate::site::header::SiteHeader;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SiteTemplate {
   pub name: String,
+  pub path: PathBuf,
   pub headers: Rc<[SiteHeader]>,
   pub body: String,
 }
@@ -0,0 +1,43 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::site::fs::SiteFs;
+
+// polls the modification times of everything under a directory; portable and
+// cheap enough for a content directory of this size
+#[derive(Debug)]
+pub struct SiteWatcher {
+  dir: PathBuf,
+  snapshot: BTreeMap<PathBuf, (SystemTime, u64)>,
+}
+
+impl SiteWatcher {
+  fn snapshot(dir: &Path) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+    SiteFs::read_all_files(dir)
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|path| {
+        let metadata = fs::metadata(&path).ok()?;
+        Some((path, (metadata.modified().ok()?, metadata.len())))
+      })
+      .collect()
+  }
+
+  pub fn new(dir: impl AsRef<Path>) -> SiteWatcher {
+    SiteWatcher {
+      dir: dir.as_ref().to_path_buf(),
+      snapshot: Self::snapshot(dir.as_ref()),
+    }
+  }
+
+  // returns every file added, removed or modified since the last call
+  pub fn changes(&mut self) -> Vec<PathBuf> {
+    let snapshot = Self::snapshot(&self.dir);
+    let mut changed: Vec<PathBuf> = snapshot.iter()
+      .filter(|(path, stamp)| self.snapshot.get(*path) != Some(stamp))
+      .map(|(path, _)| path.clone())
+      .collect();
+    changed.extend(self.snapshot.keys().filter(|path| !snapshot.contains_key(*path)).cloned());
+    self.snapshot = snapshot;
+    changed
+  }
+}
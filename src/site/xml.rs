@@ -0,0 +1,32 @@
+use regex::Regex;
+
+pub struct SiteXml;
+impl SiteXml {
+  pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+      match c {
+        '&' => escaped.push_str("&amp;"),
+        '<' => escaped.push_str("&lt;"),
+        '>' => escaped.push_str("&gt;"),
+        '"' => escaped.push_str("&quot;"),
+        '\'' => escaped.push_str("&apos;"),
+        _ => escaped.push(c),
+      }
+    }
+    escaped
+  }
+
+  pub fn element(name: &str, text: &str) -> String {
+    format!("<{}>{}</{}>", name, Self::escape(text), name)
+  }
+
+  // reads an attribute from a single (possibly minified) start tag
+  pub fn attribute(tag: &str, name: &str) -> Option<String> {
+    Regex::new(&format!(r#"\s{}=(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#, regex::escape(name)))
+      .unwrap()
+      .captures(tag)
+      .and_then(|c| c.get(1).or(c.get(2)).or(c.get(3)))
+      .map(|m| String::from(m.as_str()))
+  }
+}
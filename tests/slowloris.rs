@@ -0,0 +1,189 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const HEADER_TIMEOUT: u64 = 1;
+const IDLE_TIMEOUT: u64 = 1;
+
+// a server on a free port serving a one page site, with short head and idle
+// timeouts
+struct TestServer {
+  port: u16,
+  root: PathBuf,
+  process: Child,
+}
+
+impl TestServer {
+  fn start() -> TestServer {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).expect("The clock is before 1970").subsec_nanos();
+    let root = std::env::temp_dir().join(format!("site_v4_slowloris_{}_{}", std::process::id(), nanos));
+    let output = root.join("dist");
+    fs::create_dir_all(&output).expect("Could not create the site directory");
+    fs::write(output.join("index.html"), "<!DOCTYPE html><p>hello</p>").expect("Could not write the page");
+    fs::write(output.join("404.html"), "<!DOCTYPE html><p>missing</p>").expect("Could not write the 404 page");
+    fs::write(output.join("routes.txt"), "/ index.html\n").expect("Could not write the routes");
+
+    let port = TcpListener::bind("127.0.0.1:0").and_then(|listener| listener.local_addr()).expect("No free port").port();
+    let config = root.join("site.toml");
+    fs::write(&config, format!(
+      "[directories]\noutput = {:?}\n\n[server]\naddr = \"127.0.0.1\"\nport = {}\n\n[server.timeouts]\nheader = {}\nidle = {}\n\n[server.log]\npath = {:?}\n",
+      output, port, HEADER_TIMEOUT, IDLE_TIMEOUT, root.join("access.log"),
+    )).expect("Could not write the config");
+
+    let process = Command::new(env!("CARGO_BIN_EXE_site_v4"))
+      .args(["--config", config.to_str().expect("Temp path is not utf-8"), "serve", "--no-build"])
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+      .expect("Could not start the server");
+
+    let server = TestServer { port, root, process };
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+      assert!(started.elapsed() < Duration::from_secs(10), "The server did not start listening");
+      thread::sleep(Duration::from_millis(50));
+    }
+    server
+  }
+
+  fn connect(&self) -> TcpStream {
+    let stream = TcpStream::connect(("127.0.0.1", self.port)).expect("Could not connect");
+    stream.set_read_timeout(Some(Duration::from_secs(10))).expect("Could not set the read timeout");
+    stream
+  }
+
+  fn get(&self) -> String {
+    let mut stream = self.connect();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").expect("Could not send the request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("Could not read the response");
+    response
+  }
+
+  // waits for a line containing `needle` to be written to the access log
+  fn logged(&self, needle: &str) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(5) {
+      if fs::read_to_string(self.root.join("access.log")).is_ok_and(|log| log.contains(needle)) {
+        return true;
+      }
+      thread::sleep(Duration::from_millis(50));
+    }
+    false
+  }
+}
+
+impl Drop for TestServer {
+  fn drop(&mut self) {
+    let _ = self.process.kill();
+    let _ = self.process.wait();
+    let _ = fs::remove_dir_all(&self.root);
+  }
+}
+
+// sends a header one byte at a time until the server stops reading, and
+// returns whatever it answered with
+fn trickle(mut stream: TcpStream) -> String {
+  stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n").expect("Could not send the request line");
+  let writer = stream.try_clone().expect("Could not clone the stream");
+  thread::spawn(move || {
+    let mut writer = writer;
+    for byte in b"X-Slow: ".iter().chain([b'a'; 1000].iter()) {
+      if writer.write_all(&[*byte]).is_err() {
+        return;
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+  });
+
+  let mut response = Vec::new();
+  let mut buffer = [0; 1024];
+  while let Ok(n) = stream.read(&mut buffer) {
+    if n == 0 {
+      break;
+    }
+    response.extend_from_slice(&buffer[..n]);
+  }
+  String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn trickled_headers_get_a_408_and_the_connection_is_closed() {
+  let server = TestServer::start();
+  let started = Instant::now();
+  let response = trickle(server.connect());
+  assert!(response.starts_with("HTTP/1.1 408 Request Timeout"), "unexpected response: {:?}", response);
+  assert!(response.contains("Connection: close"));
+  assert!(started.elapsed() < Duration::from_secs(HEADER_TIMEOUT + 3), "the connection was held for {:?}", started.elapsed());
+  // the request line never finished, so the log has none to show
+  assert!(server.logged("\"-\" 408 -"), "the 408 was not logged");
+}
+
+// reads until the server closes the connection
+fn read_response(mut stream: TcpStream) -> String {
+  let mut response = Vec::new();
+  let _ = stream.read_to_end(&mut response);
+  String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn silent_clients_and_unfinished_prefaces_get_a_408() {
+  let server = TestServer::start();
+  let started = Instant::now();
+  let silent = server.connect();
+  let mut partial = server.connect();
+  partial.write_all(&b"PRI * HTTP/2.0\r\n"[..]).expect("Could not send part of the preface");
+
+  for response in [read_response(silent), read_response(partial)] {
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout"), "unexpected response: {:?}", response);
+  }
+  // the wait for the preface is part of the head timeout, not added to it
+  assert!(started.elapsed() < Duration::from_secs(2 * HEADER_TIMEOUT), "the connections were held for {:?}", started.elapsed());
+  assert!(server.logged("\"-\" 408 -"), "the 408 was not logged");
+}
+
+#[test]
+fn trickling_clients_cannot_hold_the_server() {
+  let server = TestServer::start();
+  let slow: Vec<_> = (0..16).map(|_| {
+    let stream = server.connect();
+    thread::spawn(move || trickle(stream))
+  }).collect();
+
+  // the others are still answered while the slow clients are connected
+  thread::sleep(Duration::from_millis(200));
+  let started = Instant::now();
+  assert!(server.get().starts_with("HTTP/1.1 200 OK"));
+  assert!(started.elapsed() < Duration::from_secs(HEADER_TIMEOUT));
+
+  let started = Instant::now();
+  for client in slow {
+    let response = client.join().expect("A slow client panicked");
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout"), "unexpected response: {:?}", response);
+  }
+  assert!(started.elapsed() < Duration::from_secs(HEADER_TIMEOUT + 3));
+}
+
+#[test]
+fn idle_h2c_connections_are_closed() {
+  let server = TestServer::start();
+  let mut stream = server.connect();
+  let started = Instant::now();
+  // the preface and an empty SETTINGS frame, and then nothing
+  stream.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\x00\x00\x00\x04\x00\x00\x00\x00\x00").expect("Could not send the preface");
+
+  let mut buffer = [0; 1024];
+  while let Ok(n) = stream.read(&mut buffer) {
+    if n == 0 {
+      break;
+    }
+  }
+  // a GOAWAY after the idle timeout, and the connection dropped after another
+  // one since the client never answers it
+  assert!(started.elapsed() >= Duration::from_secs(IDLE_TIMEOUT), "the connection was closed after {:?}", started.elapsed());
+  assert!(started.elapsed() < Duration::from_secs(2 * IDLE_TIMEOUT + 3), "the connection was held for {:?}", started.elapsed());
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rustls::pki_types::{CertificateDer, ServerName, pem::PemObject};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+fn free_port() -> u16 {
+  TcpListener::bind("127.0.0.1:0").and_then(|listener| listener.local_addr()).expect("No free port").port()
+}
+
+// a new self-signed certificate for localhost, with a fresh key and serial
+fn generate_cert(cert: &Path, key: &Path) {
+  let status = Command::new("openssl")
+    .args(["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes", "-days", "1"])
+    .args(["-subj", "/CN=localhost", "-addext", "subjectAltName=DNS:localhost", "-addext", "basicConstraints=critical,CA:FALSE"])
+    .arg("-keyout").arg(key)
+    .arg("-out").arg(cert)
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status()
+    .expect("Could not run openssl");
+  assert!(status.success(), "openssl could not generate a certificate");
+}
+
+fn read_cert(path: &Path) -> CertificateDer<'static> {
+  CertificateDer::from_pem_file(path).expect("Could not read the certificate")
+}
+
+// a server on free ports serving a one page site over https, with the plain
+// port only redirecting there
+struct TestServer {
+  port: u16,
+  tls_port: u16,
+  root: PathBuf,
+  process: Child,
+}
+
+impl TestServer {
+  fn start() -> TestServer {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).expect("The clock is before 1970").subsec_nanos();
+    let root = std::env::temp_dir().join(format!("site_v4_tls_{}_{}", std::process::id(), nanos));
+    let output = root.join("dist");
+    fs::create_dir_all(&output).expect("Could not create the site directory");
+    fs::write(output.join("index.html"), "<!DOCTYPE html><p>hello</p>").expect("Could not write the page");
+    fs::write(output.join("404.html"), "<!DOCTYPE html><p>missing</p>").expect("Could not write the 404 page");
+    fs::write(output.join("routes.txt"), "/ index.html\n").expect("Could not write the routes");
+    generate_cert(&root.join("cert.pem"), &root.join("key.pem"));
+
+    let (port, tls_port) = (free_port(), free_port());
+    let config = root.join("site.toml");
+    fs::write(&config, format!(
+      "[directories]\noutput = {:?}\n\n[server]\naddr = \"127.0.0.1\"\nport = {}\n\n[server.tls]\ncert = {:?}\nkey = {:?}\nport = {}\n\n[server.log]\npath = {:?}\n",
+      output, port, root.join("cert.pem"), root.join("key.pem"), tls_port, root.join("access.log"),
+    )).expect("Could not write the config");
+
+    let process = Command::new(env!("CARGO_BIN_EXE_site_v4"))
+      .args(["--config", config.to_str().expect("Temp path is not utf-8"), "serve", "--no-build"])
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+      .expect("Could not start the server");
+
+    let server = TestServer { port, tls_port, root, process };
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", tls_port)).is_err() || TcpStream::connect(("127.0.0.1", port)).is_err() {
+      assert!(started.elapsed() < Duration::from_secs(10), "The server did not start listening");
+      thread::sleep(Duration::from_millis(50));
+    }
+    server
+  }
+
+  fn cert_path(&self) -> PathBuf {
+    self.root.join("cert.pem")
+  }
+
+  // a tls connection trusting the certificate currently on disk, offering
+  // `protocols` through ALPN
+  fn connect(&self, protocols: &[&[u8]]) -> StreamOwned<ClientConnection, TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots.add(read_cert(&self.cert_path())).expect("Could not trust the certificate");
+    let mut config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+      .with_safe_default_protocol_versions()
+      .expect("The ring provider supports the default protocol versions")
+      .with_root_certificates(roots)
+      .with_no_client_auth();
+    config.alpn_protocols = protocols.iter().map(|protocol| protocol.to_vec()).collect();
+
+    let name = ServerName::try_from("localhost").expect("localhost is a valid name");
+    let connection = ClientConnection::new(Arc::new(config), name).expect("Could not start the handshake");
+    let socket = TcpStream::connect(("127.0.0.1", self.tls_port)).expect("Could not connect");
+    socket.set_read_timeout(Some(Duration::from_secs(10))).expect("Could not set the read timeout");
+    let mut stream = StreamOwned::new(connection, socket);
+    while stream.conn.is_handshaking() {
+      stream.conn.complete_io(&mut stream.sock).expect("The handshake failed");
+    }
+    stream
+  }
+
+  // sends a request to the plain port and returns the whole response
+  fn plain_get(&self, host: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", self.port)).expect("Could not connect");
+    stream.set_read_timeout(Some(Duration::from_secs(10))).expect("Could not set the read timeout");
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host).expect("Could not send the request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("Could not read the response");
+    response
+  }
+}
+
+impl Drop for TestServer {
+  fn drop(&mut self) {
+    let _ = self.process.kill();
+    let _ = self.process.wait();
+    let _ = fs::remove_dir_all(&self.root);
+  }
+}
+
+// reads until the server closes the connection, which it may do without a
+// close_notify
+fn read_all(stream: &mut impl Read) -> String {
+  let mut response = Vec::new();
+  let mut buffer = [0; 4096];
+  while let Ok(n) = stream.read(&mut buffer) {
+    if n == 0 {
+      break;
+    }
+    response.extend_from_slice(&buffer[..n]);
+  }
+  String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn alpn_chooses_h2_when_offered_and_http_1_1_otherwise() {
+  let server = TestServer::start();
+  assert_eq!(server.connect(&[b"h2", b"http/1.1"]).conn.alpn_protocol(), Some(&b"h2"[..]));
+
+  let mut stream = server.connect(&[b"http/1.1"]);
+  assert_eq!(stream.conn.alpn_protocol(), Some(&b"http/1.1"[..]));
+  stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").expect("Could not send the request");
+  let response = read_all(&mut stream);
+  assert!(response.starts_with("HTTP/1.1 200 OK"), "unexpected response: {:?}", response);
+  assert!(response.contains("Strict-Transport-Security: max-age="));
+  assert!(response.ends_with("<!DOCTYPE html><p>hello</p>"));
+}
+
+#[test]
+fn a_replaced_certificate_is_used_without_a_restart() {
+  let server = TestServer::start();
+  let first = read_cert(&server.cert_path());
+  assert_eq!(server.connect(&[b"http/1.1"]).conn.peer_certificates().expect("No certificate was sent")[0], first);
+
+  generate_cert(&server.cert_path(), &server.root.join("key.pem"));
+  // some filesystems only keep modification times to the second
+  let later = SystemTime::now() + Duration::from_secs(2);
+  for file in ["cert.pem", "key.pem"] {
+    fs::File::options().write(true).open(server.root.join(file)).and_then(|file| file.set_modified(later)).expect("Could not touch the file");
+  }
+  let second = read_cert(&server.cert_path());
+  assert_ne!(first, second);
+  assert_eq!(server.connect(&[b"http/1.1"]).conn.peer_certificates().expect("No certificate was sent")[0], second);
+}
+
+#[test]
+fn the_plain_port_redirects_to_https() {
+  let server = TestServer::start();
+  let response = server.plain_get("localhost:8000", "/blog?page=2");
+  assert!(response.starts_with("HTTP/1.1 308 Permanent Redirect"), "unexpected response: {:?}", response);
+  assert!(response.contains(&format!("Location: https://localhost:{}/blog?page=2\r\n", server.tls_port)));
+
+  let response = server.plain_get("[::1]:8000", "/");
+  assert!(response.contains(&format!("Location: https://[::1]:{}/\r\n", server.tls_port)), "unexpected response: {:?}", response);
+
+  let mut stream = TcpStream::connect(("127.0.0.1", server.port)).expect("Could not connect");
+  stream.write_all(b"GET / HTTP/1.0\r\n\r\n").expect("Could not send the request");
+  let mut response = String::new();
+  stream.read_to_string(&mut response).expect("Could not read the response");
+  assert!(response.starts_with("HTTP/1.0 400 Bad Request"), "unexpected response: {:?}", response);
+}